@@ -1,8 +1,17 @@
-use super::verify_file;
-use crate::{b64_decode, b64_encode, CmdExecutor};
-use anyhow::Result;
+use super::{check_overwrite, verify_file};
+use crate::{
+    b64_decode, b64_encode, datauri_decode, datauri_encode, get_progress_reader, get_reader,
+    hexdump, is_binary, mime_extension, CmdExecutor,
+};
+use anyhow::{bail, Result};
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
@@ -12,6 +21,9 @@ pub enum B64SubCommand {
 
     #[command(about = "Decode base64 data.")]
     Decode(B64DecodeOpts),
+
+    #[command(about = "Convert a file to a data URI, or a data URI back to a file.")]
+    Datauri(B64DataUriOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -21,6 +33,13 @@ pub struct B64EncodeOpts {
 
     #[arg(short, long, value_parser = parse_b64format, default_value = "std")]
     pub format: B64Format,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report progress and throughput on stderr"
+    )]
+    pub progress: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -30,30 +49,193 @@ pub struct B64DecodeOpts {
 
     #[arg(short, long, value_parser = parse_b64format, default_value = "std")]
     pub format: B64Format,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Accept either alphabet, line breaks and missing or extra padding"
+    )]
+    pub lenient: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Write the decoded bytes to a file instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Overwrite --output, or write binary data to a terminal"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print a hexdump of the decoded bytes"
+    )]
+    pub hexdump: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Report progress and throughput on stderr"
+    )]
+    pub progress: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct B64DataUriOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Parse a data URI and write its content to --output"
+    )]
+    pub decode: bool,
+
+    #[arg(
+        short,
+        long,
+        default_value = "output",
+        help = "Output file for --decode, the extension is added from the MIME type if missing, - for stdout"
+    )]
+    pub output: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Overwrite --output, or write binary data to a terminal"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "Use this MIME type instead of sniffing it from the input"
+    )]
+    pub mime: Option<String>,
 }
 
 #[derive(Debug, Parser, Clone, Copy)]
 pub enum B64Format {
     Std,
+    StdNoPad,
     Url,
+    UrlPad,
+    // standard alphabet wrapped at 76 columns with CRLF
+    Mime,
+    // standard alphabet wrapped at 64 columns with LF
+    Pem,
+    // decode only, detects base64 variants, hex and base32
+    Auto,
 }
 
 fn parse_b64format(s: &str) -> Result<B64Format, &'static str> {
-    match s {
-        "std" => Ok(B64Format::Std),
-        "url" => Ok(B64Format::Url),
-        _ => Err("invalid base64 format"),
+    s.parse()
+}
+
+impl FromStr for B64Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "std" => Ok(B64Format::Std),
+            "std-nopad" => Ok(B64Format::StdNoPad),
+            "url" => Ok(B64Format::Url),
+            "url-pad" => Ok(B64Format::UrlPad),
+            "mime" => Ok(B64Format::Mime),
+            "pem" => Ok(B64Format::Pem),
+            "auto" => Ok(B64Format::Auto),
+            _ => Err(
+                "invalid base64 format, expect one of std, std-nopad, url, url-pad, mime, pem, auto",
+            ),
+        }
     }
 }
 
 impl CmdExecutor for B64EncodeOpts {
     async fn execute(&self) -> Result<()> {
-        b64_encode(&self.input, self.format)
+        let mut reader = get_progress_reader(&self.input, self.progress)?;
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        b64_encode(&mut reader, &mut stdout, self.format)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+        Ok(())
     }
 }
 
 impl CmdExecutor for B64DecodeOpts {
     async fn execute(&self) -> Result<()> {
-        b64_decode(&self.input, self.format)
+        let mut reader = get_progress_reader(&self.input, self.progress)?;
+        if let Some(output) = &self.output {
+            check_overwrite(output, self.force)?;
+            let mut file = BufWriter::new(File::create(output)?);
+            b64_decode(&mut reader, &mut file, self.format, self.lenient)?;
+            file.flush()?;
+            return Ok(());
+        }
+        let stdout = io::stdout().lock();
+        if !self.hexdump && !stdout.is_terminal() {
+            let mut stdout = BufWriter::new(stdout);
+            b64_decode(&mut reader, &mut stdout, self.format, self.lenient)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+        // a terminal or a hexdump needs the decoded bytes at hand
+        let mut decoded = Vec::new();
+        b64_decode(&mut reader, &mut decoded, self.format, self.lenient)?;
+        let mut stdout = stdout;
+        if self.hexdump {
+            writeln!(stdout, "{}", hexdump(&decoded))?;
+            return Ok(());
+        }
+        if !self.force && is_binary(&decoded) {
+            bail!(
+                "refusing to write binary data to a terminal, use --output, --hexdump or --force"
+            );
+        }
+        stdout.write_all(&decoded)?;
+        if !decoded.ends_with(b"\n") {
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for B64DataUriOpts {
+    async fn execute(&self) -> Result<()> {
+        let mut content = Vec::new();
+        get_reader(&self.input)?.read_to_end(&mut content)?;
+        if !self.decode {
+            let path = (self.input != "-").then(|| Path::new(&self.input));
+            println!("{}", datauri_encode(&content, path, self.mime.as_deref()));
+            return Ok(());
+        }
+        let uri = datauri_decode(std::str::from_utf8(&content)?)?;
+        if self.output == Path::new("-") {
+            let mut stdout = io::stdout().lock();
+            if !self.force && stdout.is_terminal() && is_binary(&uri.data) {
+                bail!("refusing to write binary data to a terminal, use --output or --force");
+            }
+            stdout.write_all(&uri.data)?;
+            return Ok(());
+        }
+        let mut output = self.output.clone();
+        if output.extension().is_none() {
+            output.set_extension(mime_extension(&uri.mime));
+        }
+        check_overwrite(&output, self.force)?;
+        fs::write(&output, &uri.data)?;
+        eprintln!(
+            "wrote {} bytes of {} to {}",
+            uri.data.len(),
+            uri.mime,
+            output.display()
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,194 @@
+use super::{check_overwrite, verify_file};
+use crate::{get_codec, get_reader, is_binary, CmdExecutor};
+use anyhow::{bail, Result};
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CodecSubCommand {
+    #[command(about = "Encode data with a binary-to-text or escaping codec")]
+    Encode(CodecEncodeOpts),
+
+    #[command(about = "Decode data with a binary-to-text or escaping codec")]
+    Decode(CodecDecodeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CodecEncodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, value_parser = parse_codec_name)]
+    pub codec: CodecName,
+
+    #[arg(
+        short,
+        long,
+        help = "Write the encoded text to a file instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, default_value_t = false, help = "Overwrite --output")]
+    pub force: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct CodecDecodeOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, value_parser = parse_codec_name)]
+    pub codec: CodecName,
+
+    #[arg(
+        short,
+        long,
+        help = "Write the decoded bytes to a file instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Overwrite --output, or write binary data to a terminal"
+    )]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CodecName {
+    Hex,
+    HexUpper,
+    Base32,
+    Base32Crockford,
+    Base58,
+    Base58Check,
+    Base85,
+    Z85,
+    Ascii85,
+    Base64,
+    Base64NoPad,
+    Base64Url,
+    Base64UrlPad,
+    // text escaping rather than binary-to-text
+    Url,
+    UrlComponent,
+    Form,
+    Html,
+    JsonString,
+    UnicodeEscape,
+    // decode only, picks one of the codecs above from the input
+    Auto,
+}
+
+fn parse_codec_name(s: &str) -> Result<CodecName, &'static str> {
+    s.parse()
+}
+
+impl FromStr for CodecName {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(CodecName::Hex),
+            "hex-upper" => Ok(CodecName::HexUpper),
+            "base32" => Ok(CodecName::Base32),
+            "base32-crockford" => Ok(CodecName::Base32Crockford),
+            "base58" => Ok(CodecName::Base58),
+            "base58check" => Ok(CodecName::Base58Check),
+            "base85" => Ok(CodecName::Base85),
+            "z85" => Ok(CodecName::Z85),
+            "ascii85" => Ok(CodecName::Ascii85),
+            "base64" => Ok(CodecName::Base64),
+            "base64-nopad" => Ok(CodecName::Base64NoPad),
+            "base64url" => Ok(CodecName::Base64Url),
+            "base64url-pad" => Ok(CodecName::Base64UrlPad),
+            "url" => Ok(CodecName::Url),
+            "url-component" => Ok(CodecName::UrlComponent),
+            "form" => Ok(CodecName::Form),
+            "html" => Ok(CodecName::Html),
+            "json-string" => Ok(CodecName::JsonString),
+            "unicode-escape" => Ok(CodecName::UnicodeEscape),
+            "auto" => Ok(CodecName::Auto),
+            _ => Err("invalid codec, expect one of hex, hex-upper, base32, base32-crockford, base58, base58check, base85, z85, ascii85, base64, base64-nopad, base64url, base64url-pad, url, url-component, form, html, json-string, unicode-escape, auto"),
+        }
+    }
+}
+
+impl Display for CodecName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecName::Hex => write!(f, "hex"),
+            CodecName::HexUpper => write!(f, "hex-upper"),
+            CodecName::Base32 => write!(f, "base32"),
+            CodecName::Base32Crockford => write!(f, "base32-crockford"),
+            CodecName::Base58 => write!(f, "base58"),
+            CodecName::Base58Check => write!(f, "base58check"),
+            CodecName::Base85 => write!(f, "base85"),
+            CodecName::Z85 => write!(f, "z85"),
+            CodecName::Ascii85 => write!(f, "ascii85"),
+            CodecName::Base64 => write!(f, "base64"),
+            CodecName::Base64NoPad => write!(f, "base64-nopad"),
+            CodecName::Base64Url => write!(f, "base64url"),
+            CodecName::Base64UrlPad => write!(f, "base64url-pad"),
+            CodecName::Url => write!(f, "url"),
+            CodecName::UrlComponent => write!(f, "url-component"),
+            CodecName::Form => write!(f, "form"),
+            CodecName::Html => write!(f, "html"),
+            CodecName::JsonString => write!(f, "json-string"),
+            CodecName::UnicodeEscape => write!(f, "unicode-escape"),
+            CodecName::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl CmdExecutor for CodecEncodeOpts {
+    async fn execute(&self) -> Result<()> {
+        let codec = get_codec(self.codec)?;
+        let mut reader = get_reader(&self.input)?;
+        if let Some(output) = &self.output {
+            check_overwrite(output, self.force)?;
+            let mut file = File::create(output)?;
+            return codec.encode(&mut reader, &mut file);
+        }
+        let mut stdout = io::stdout().lock();
+        codec.encode(&mut reader, &mut stdout)?;
+        writeln!(stdout)?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CodecDecodeOpts {
+    async fn execute(&self) -> Result<()> {
+        let codec = get_codec(self.codec)?;
+        let mut reader = get_reader(&self.input)?;
+        if let Some(output) = &self.output {
+            check_overwrite(output, self.force)?;
+            let mut file = File::create(output)?;
+            return codec.decode(&mut reader, &mut file);
+        }
+        let mut stdout = io::stdout().lock();
+        if !stdout.is_terminal() {
+            return codec.decode(&mut reader, &mut stdout);
+        }
+        // buffer what goes to a terminal, so binary data can be caught before it is printed
+        let mut decoded = Vec::new();
+        codec.decode(&mut reader, &mut decoded)?;
+        if !self.force && is_binary(&decoded) {
+            bail!("refusing to write binary data to a terminal, use --output or --force");
+        }
+        stdout.write_all(&decoded)?;
+        if !decoded.ends_with(b"\n") {
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    gen_nanoid, gen_token, gen_ulid, gen_uuid_v4, gen_uuid_v7, verify_token, CmdExecutor,
+    NANOID_ALPHABET,
+};
+use anyhow::Result;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenSubCommand {
+    #[command(about = "Generate UUIDs, v4 (random) or v7 (time ordered)")]
+    Uuid(GenUuidOpts),
+
+    #[command(about = "Generate ULIDs")]
+    Ulid(GenUlidOpts),
+
+    #[command(about = "Generate NanoIDs")]
+    Nanoid(GenNanoidOpts),
+
+    #[command(about = "Generate random API tokens")]
+    Token(GenTokenOpts),
+
+    #[command(about = "Verify the checksum tail of a token")]
+    VerifyToken(VerifyTokenOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenUuidOpts {
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+
+    #[arg(long, value_parser = verify_uuid_version, default_value_t = 4)]
+    pub version: u8,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenUlidOpts {
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenNanoidOpts {
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+
+    #[arg(short, long, default_value_t = 21)]
+    pub length: usize,
+
+    #[arg(short, long, default_value = NANOID_ALPHABET)]
+    pub alphabet: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenTokenOpts {
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+
+    #[arg(short, long, default_value_t = 32, help = "Number of random bytes")]
+    pub length: usize,
+
+    #[arg(short, long, value_parser = parse_token_encoding, default_value = "base58")]
+    pub encoding: TokenEncoding,
+
+    #[arg(short, long, default_value = "", help = "Prefix such as ghp_")]
+    pub prefix: String,
+
+    #[arg(long, default_value_t = false, help = "Append a CRC32 checksum")]
+    pub checksum: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct VerifyTokenOpts {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TokenEncoding {
+    Hex,
+    Base64Url,
+    Base58,
+}
+
+fn parse_token_encoding(s: &str) -> Result<TokenEncoding, &'static str> {
+    match s {
+        "hex" => Ok(TokenEncoding::Hex),
+        "base64url" => Ok(TokenEncoding::Base64Url),
+        "base58" => Ok(TokenEncoding::Base58),
+        _ => Err("invalid token encoding"),
+    }
+}
+
+fn verify_uuid_version(s: &str) -> Result<u8, &'static str> {
+    match s {
+        "4" => Ok(4),
+        "7" => Ok(7),
+        _ => Err("only uuid v4 and v7 are supported"),
+    }
+}
+
+impl CmdExecutor for GenUuidOpts {
+    async fn execute(&self) -> Result<()> {
+        for _ in 0..self.count {
+            let uuid = match self.version {
+                7 => gen_uuid_v7()?,
+                _ => gen_uuid_v4(),
+            };
+            println!("{uuid}");
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenUlidOpts {
+    async fn execute(&self) -> Result<()> {
+        for _ in 0..self.count {
+            println!("{}", gen_ulid()?);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenNanoidOpts {
+    async fn execute(&self) -> Result<()> {
+        for _ in 0..self.count {
+            println!("{}", gen_nanoid(self.length, &self.alphabet)?);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenTokenOpts {
+    async fn execute(&self) -> Result<()> {
+        for _ in 0..self.count {
+            let token = gen_token(self.length, self.encoding, &self.prefix, self.checksum)?;
+            println!("{token}");
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for VerifyTokenOpts {
+    async fn execute(&self) -> Result<()> {
+        println!("{}", verify_token(&self.token));
+        Ok(())
+    }
+}
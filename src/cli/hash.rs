@@ -0,0 +1,166 @@
+use super::verify_file;
+use crate::{
+    check_manifest, collect_files, get_reader, hash_files, hash_reader, manifest_line,
+    parse_manifest, CheckStatus, CmdExecutor,
+};
+use anyhow::{bail, Result};
+use clap::Parser;
+use std::{fmt::Display, io::Read, path::Path, str::FromStr};
+
+#[derive(Debug, Parser)]
+pub struct HashOpts {
+    #[arg(
+        help = "Files or directories to hash, - for stdin",
+        default_value = "-"
+    )]
+    pub paths: Vec<String>,
+
+    #[arg(short, long, value_parser = parse_hash_alg, default_value = "blake3")]
+    pub alg: HashAlg,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "Hash the files in directories and their subdirectories"
+    )]
+    pub recursive: bool,
+
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        help = "Check the files listed in a sha256sum/b3sum style manifest, paths given are searched for new files"
+    )]
+    pub check: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "With --check, only print files that are not OK"
+    )]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HashAlg {
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha1,
+    Md5,
+    Crc32,
+}
+
+impl HashAlg {
+    pub fn hex_len(&self) -> usize {
+        match self {
+            HashAlg::Blake3 | HashAlg::Sha256 => 64,
+            HashAlg::Sha512 => 128,
+            HashAlg::Sha1 => 40,
+            HashAlg::Md5 => 32,
+            HashAlg::Crc32 => 8,
+        }
+    }
+}
+
+fn parse_hash_alg(s: &str) -> Result<HashAlg, &'static str> {
+    s.parse()
+}
+
+impl FromStr for HashAlg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(HashAlg::Blake3),
+            "sha256" => Ok(HashAlg::Sha256),
+            "sha512" => Ok(HashAlg::Sha512),
+            "sha1" => Ok(HashAlg::Sha1),
+            "md5" => Ok(HashAlg::Md5),
+            "crc32" => Ok(HashAlg::Crc32),
+            _ => Err(
+                "invalid hash algorithm, expect one of blake3, sha256, sha512, sha1, md5, crc32",
+            ),
+        }
+    }
+}
+
+impl Display for HashAlg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlg::Blake3 => write!(f, "blake3"),
+            HashAlg::Sha256 => write!(f, "sha256"),
+            HashAlg::Sha512 => write!(f, "sha512"),
+            HashAlg::Sha1 => write!(f, "sha1"),
+            HashAlg::Md5 => write!(f, "md5"),
+            HashAlg::Crc32 => write!(f, "crc32"),
+        }
+    }
+}
+
+impl CmdExecutor for HashOpts {
+    async fn execute(&self) -> Result<()> {
+        if let Some(manifest) = &self.check {
+            return self.check(manifest);
+        }
+        if self.paths == ["-"] {
+            let mut reader = get_reader("-")?;
+            println!(
+                "{}",
+                manifest_line(&hash_reader(&mut reader, self.alg)?, Path::new("-"))
+            );
+            return Ok(());
+        }
+        let files = collect_files(&self.paths, self.recursive)?;
+        let mut errors = 0;
+        for (file, hash) in files.iter().zip(hash_files(&files, self.alg)) {
+            match hash {
+                Ok(hash) => println!("{}", manifest_line(&hash, file)),
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    errors += 1;
+                }
+            }
+        }
+        if errors > 0 {
+            bail!("{} of {} files could not be hashed", errors, files.len());
+        }
+        Ok(())
+    }
+}
+
+impl HashOpts {
+    fn check(&self, manifest: &str) -> Result<()> {
+        let mut content = String::new();
+        get_reader(manifest)?.read_to_string(&mut content)?;
+        let entries = parse_manifest(&content)?;
+        // paths only matter for finding new files, stdin has none
+        let paths: Vec<String> = self.paths.iter().filter(|p| *p != "-").cloned().collect();
+        let checked = check_manifest(&entries, self.alg, &paths)?;
+        for entry in &checked {
+            if !(self.quiet && entry.status == CheckStatus::Ok) {
+                println!("{}: {}", entry.path.display(), entry.status);
+            }
+        }
+        let count = |status| checked.iter().filter(|e| e.status == status).count();
+        let (failed, missing, new) = (
+            count(CheckStatus::Failed),
+            count(CheckStatus::Missing),
+            count(CheckStatus::New),
+        );
+        if new > 0 {
+            eprintln!("{} files not listed in the manifest", new);
+        }
+        if failed + missing > 0 {
+            bail!(
+                "{} of {} files did not match, {} missing",
+                failed,
+                entries.len(),
+                missing
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use super::verify_file;
+use crate::{
+    age_recipient_encode, get_data, key_decode, key_decode_as, key_encode, key_format_detect,
+    key_load, key_write, read_new_passphrase, read_passphrase, write_private_file, CmdExecutor,
+    KeyMaterial, Keyring, Keystore, KeystoreKdf, X25519Recipient, NEW_PASSPHRASE_ENV,
+    PASSPHRASE_ENV,
+};
+use anyhow::Result;
+use clap::Parser;
+use data_encoding::HEXLOWER;
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use time::format_description::well_known::Rfc3339;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum KeySubCommand {
+    #[command(about = "Convert a key between raw, hex, PEM/PKCS#8, OpenSSH and JWK files")]
+    Convert(KeyConvertOpts),
+    #[command(about = "Change the passphrase of a keystore file")]
+    Passwd(KeyPasswdOpts),
+
+    #[command(about = "List the keys in the keyring")]
+    List(KeyListOpts),
+
+    #[command(about = "Add a key file to the keyring under a name")]
+    Import(KeyImportOpts),
+
+    #[command(about = "Write a key from the keyring, as stored or converted")]
+    Export(KeyExportOpts),
+
+    #[command(about = "Remove a key from the keyring")]
+    Delete(KeyDeleteOpts),
+
+    #[command(about = "Show what the keyring knows about a key")]
+    Show(KeyShowOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyConvertOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(long, value_parser = parse_key_format, help = "raw, hex, pem, openssh, jwk, or keystore to seal it with a passphrase")]
+    pub to: KeyFormat,
+
+    #[arg(
+        long,
+        value_parser = parse_key_format,
+        help = "Format of the input, detected if not given, needed to read 64 hex digits as hex or raw"
+    )]
+    pub from: Option<KeyFormat>,
+
+    #[arg(
+        long,
+        value_parser = parse_key_kind,
+        help = "What a raw or hex key is: blake3, ed25519-sign or ed25519-verify"
+    )]
+    pub kind: Option<KeyKind>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Write the public half of a private key"
+    )]
+    pub public: bool,
+
+    #[arg(short, long, help = "Output file, stdout if not given")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyPasswdOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyListOpts {}
+
+#[derive(Debug, Parser)]
+pub struct KeyImportOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    #[arg(short, long, help = "Name to address the key by, as @name")]
+    pub name: String,
+
+    #[arg(
+        long,
+        value_parser = parse_key_kind,
+        help = "What a raw or hex key is: blake3, ed25519-sign or ed25519-verify"
+    )]
+    pub kind: Option<KeyKind>,
+
+    #[arg(
+        long,
+        value_parser = parse_key_trust,
+        default_value = "unknown",
+        help = "How far the key's owner is trusted: unknown, never, marginal or full"
+    )]
+    pub trust: KeyTrust,
+
+    #[arg(long, help = "Who or what the key belongs to")]
+    pub comment: Option<String>,
+
+    #[arg(long, default_value_t = false, help = "Replace a key of the same name")]
+    pub force: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyExportOpts {
+    #[arg(help = "Name or fingerprint of the key")]
+    pub name: String,
+
+    #[arg(long, value_parser = parse_key_format, help = "Convert to raw, hex, pem, openssh, jwk or keystore, the stored file if not given")]
+    pub to: Option<KeyFormat>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Write the public half of a private key"
+    )]
+    pub public: bool,
+
+    #[arg(short, long, help = "Output file, stdout if not given")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyDeleteOpts {
+    #[arg(help = "Name or fingerprint of the key")]
+    pub name: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyShowOpts {
+    #[arg(help = "Name or fingerprint of the key")]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    Raw,
+    Hex,
+    Pem,
+    Openssh,
+    Jwk,
+    Keystore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyKind {
+    Blake3,
+    Ed25519Sign,
+    Ed25519Verify,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyTrust {
+    Unknown,
+    Never,
+    Marginal,
+    Full,
+}
+
+pub fn parse_key_format(s: &str) -> Result<KeyFormat, &'static str> {
+    s.parse()
+}
+
+fn parse_key_kind(s: &str) -> Result<KeyKind, &'static str> {
+    s.parse()
+}
+
+fn parse_key_trust(s: &str) -> Result<KeyTrust, &'static str> {
+    s.parse()
+}
+
+impl FromStr for KeyFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(KeyFormat::Raw),
+            "hex" => Ok(KeyFormat::Hex),
+            "pem" => Ok(KeyFormat::Pem),
+            "openssh" => Ok(KeyFormat::Openssh),
+            "jwk" => Ok(KeyFormat::Jwk),
+            "keystore" => Ok(KeyFormat::Keystore),
+            _ => Err("invalid key format, expect one of raw, hex, pem, openssh, jwk, keystore"),
+        }
+    }
+}
+
+impl Display for KeyFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyFormat::Raw => write!(f, "raw"),
+            KeyFormat::Hex => write!(f, "hex"),
+            KeyFormat::Pem => write!(f, "pem"),
+            KeyFormat::Openssh => write!(f, "openssh"),
+            KeyFormat::Jwk => write!(f, "jwk"),
+            KeyFormat::Keystore => write!(f, "keystore"),
+        }
+    }
+}
+
+impl FromStr for KeyKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(KeyKind::Blake3),
+            "ed25519-sign" => Ok(KeyKind::Ed25519Sign),
+            "ed25519-verify" => Ok(KeyKind::Ed25519Verify),
+            _ => Err("invalid key kind, expect one of blake3, ed25519-sign, ed25519-verify"),
+        }
+    }
+}
+
+impl Display for KeyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyKind::Blake3 => write!(f, "blake3"),
+            KeyKind::Ed25519Sign => write!(f, "ed25519-sign"),
+            KeyKind::Ed25519Verify => write!(f, "ed25519-verify"),
+        }
+    }
+}
+
+impl FromStr for KeyTrust {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unknown" => Ok(KeyTrust::Unknown),
+            "never" => Ok(KeyTrust::Never),
+            "marginal" => Ok(KeyTrust::Marginal),
+            "full" => Ok(KeyTrust::Full),
+            _ => Err("invalid trust, expect one of unknown, never, marginal, full"),
+        }
+    }
+}
+
+impl Display for KeyTrust {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyTrust::Unknown => write!(f, "unknown"),
+            KeyTrust::Never => write!(f, "never"),
+            KeyTrust::Marginal => write!(f, "marginal"),
+            KeyTrust::Full => write!(f, "full"),
+        }
+    }
+}
+
+// writes a converted key to a file or stdout, sealing it for a keystore
+fn key_output(key: &KeyMaterial, to: KeyFormat, output: Option<&Path>) -> Result<()> {
+    if to == KeyFormat::Keystore {
+        let passphrase = read_new_passphrase("New passphrase: ", NEW_PASSPHRASE_ENV)?;
+        let store = Keystore::seal(key, &passphrase, KeystoreKdf::default())?;
+        return match output {
+            Some(output) => store.write(output),
+            None => Ok(io::stdout().write_all(store.to_json()?.as_bytes())?),
+        };
+    }
+    match output {
+        Some(output) => key_write(output, key, to),
+        None => Ok(io::stdout().write_all(&key_encode(key, to)?)?),
+    }
+}
+
+impl CmdExecutor for KeyConvertOpts {
+    async fn execute(&self) -> Result<()> {
+        let data = get_data(&self.input)?;
+        let (from, mut key) = match self.from {
+            Some(from) => (from, key_decode_as(&data, self.kind, from)?),
+            None => (key_format_detect(&data), key_decode(&data, self.kind)?),
+        };
+        if self.public {
+            key = key.public()?;
+        }
+        key_output(&key, self.to, self.output.as_deref())?;
+        if let Some(output) = &self.output {
+            eprintln!(
+                "{} key converted from {} to {}, written to {}",
+                key.kind(),
+                from,
+                self.to,
+                output.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyPasswdOpts {
+    async fn execute(&self) -> Result<()> {
+        let store = Keystore::read(&self.input)?;
+        let key = store.open(&read_passphrase("Current passphrase: ", PASSPHRASE_ENV)?)?;
+        let passphrase = read_new_passphrase("New passphrase: ", NEW_PASSPHRASE_ENV)?;
+        let store = Keystore::seal(&key, &passphrase, store.kdf.renew())?;
+        // write next to the keystore and rename, so a failure never loses the key
+        let tmp = format!("{}.tmp", self.input);
+        write_private_file(tmp.as_ref(), store.to_json()?.as_bytes())?;
+        std::fs::rename(&tmp, &self.input)?;
+        eprintln!("passphrase of {} changed", self.input);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyListOpts {
+    async fn execute(&self) -> Result<()> {
+        let keyring = Keyring::open()?;
+        if keyring.keys.is_empty() {
+            eprintln!("no keys in {}", keyring.dir().display());
+            return Ok(());
+        }
+        println!(
+            "{:<20} {:<15} {:<16} {:<9} COMMENT",
+            "NAME", "KIND", "FINGERPRINT", "TRUST"
+        );
+        for entry in &keyring.keys {
+            println!(
+                "{:<20} {:<15} {:<16} {:<9} {}",
+                entry.name,
+                entry.kind,
+                entry.short_fingerprint(),
+                entry.trust,
+                entry.comment.as_deref().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyImportOpts {
+    async fn execute(&self) -> Result<()> {
+        let mut keyring = Keyring::open()?;
+        let data = get_data(&self.input)?;
+        let entry = keyring.import(
+            &self.name,
+            &data,
+            self.kind,
+            self.trust,
+            self.comment.clone(),
+            self.force,
+        )?;
+        eprintln!(
+            "imported {} key {} as @{}",
+            entry.kind, entry.fingerprint, entry.name
+        );
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyExportOpts {
+    async fn execute(&self) -> Result<()> {
+        let keyring = Keyring::open()?;
+        let kind = self.public.then_some(KeyKind::Ed25519Verify);
+        let entry = keyring.find(&self.name, kind)?;
+        let path = keyring.path(entry);
+        let path = path.to_string_lossy();
+        let (to, public) = match (self.to, self.public) {
+            (None, false) => {
+                let data = get_data(&path)?;
+                match &self.output {
+                    Some(output) => fs::copy(path.as_ref(), output).map(|_| ())?,
+                    None => io::stdout().write_all(&data)?,
+                }
+                return Ok(());
+            }
+            (to, public) => (to.unwrap_or(KeyFormat::Raw), public),
+        };
+        let mut key = key_load(&path, entry.kind)?;
+        if public {
+            key = key.public()?;
+        }
+        key_output(&key, to, self.output.as_deref())
+    }
+}
+
+impl CmdExecutor for KeyDeleteOpts {
+    async fn execute(&self) -> Result<()> {
+        let entry = Keyring::open()?.delete(&self.name)?;
+        eprintln!("deleted {} key @{}", entry.kind, entry.name);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyShowOpts {
+    async fn execute(&self) -> Result<()> {
+        let keyring = Keyring::open()?;
+        let entry = keyring.find(&self.name, None)?;
+        let path = keyring.path(entry);
+        println!("name:        {}", entry.name);
+        println!("kind:        {}", entry.kind);
+        println!("fingerprint: {}", entry.fingerprint);
+        println!("trust:       {}", entry.trust);
+        if let Some(comment) = &entry.comment {
+            println!("comment:     {comment}");
+        }
+        println!("added:       {}", entry.added.format(&Rfc3339)?);
+        println!("file:        {} ({})", path.display(), entry.format);
+        // a sealed signing key asks for its passphrase to give its public half
+        if entry.kind != KeyKind::Blake3 {
+            let public = key_load(&path.to_string_lossy(), KeyKind::Ed25519Verify)?;
+            println!("public key:  {}", HEXLOWER.encode(&public.to_bytes()));
+            if let KeyMaterial::Ed25519Verify(public) = public {
+                let recipient = X25519Recipient::from_ed25519(&public);
+                println!("x25519:      {}", HEXLOWER.encode(&recipient.to_bytes()));
+                println!("age:         {}", age_recipient_encode(&recipient)?);
+            }
+        }
+        Ok(())
+    }
+}
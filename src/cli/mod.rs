@@ -3,17 +3,29 @@ use enum_dispatch::enum_dispatch;
 use std::path::{Path, PathBuf};
 
 mod b64;
+mod codec;
 mod csv;
+mod gen_id;
+mod hash;
 mod http;
 mod jwt;
+mod key;
+mod otp;
 mod pass;
+mod pipe;
 mod text;
 
 pub use b64::*;
+pub use codec::*;
 pub use csv::*;
+pub use gen_id::*;
+pub use hash::*;
 pub use http::*;
 pub use jwt::*;
+pub use key::*;
+pub use otp::*;
 pub use pass::*;
+pub use pipe::*;
 pub use text::*;
 
 #[derive(Parser, Debug)]
@@ -41,14 +53,38 @@ pub enum SubCommand {
     #[command(subcommand, about = "Encode or decode data to/from base64.")]
     B64(B64SubCommand),
 
+    #[command(
+        subcommand,
+        about = "Encode or decode data with hex, base32, base58, base85, URL/HTML/JSON escaping and more."
+    )]
+    Codec(CodecSubCommand),
+
     #[command(subcommand, about = "Sign or verify text.")]
     Text(TextSubCommand),
 
+    #[command(
+        subcommand,
+        about = "Convert signing keys between file formats, or manage the local keyring."
+    )]
+    Key(KeySubCommand),
+
     #[command(subcommand, about = "Send HTTP requests.")]
     Http(HttpSubCommand),
 
     #[command(subcommand, about = "Sign or verify JWT.")]
     Jwt(JwtSubCommand),
+
+    #[command(subcommand, about = "Generate or verify TOTP/HOTP one-time codes.")]
+    Otp(OtpSubCommand),
+
+    #[command(subcommand, about = "Generate UUIDs, ULIDs, NanoIDs or API tokens.")]
+    Gen(GenSubCommand),
+
+    #[command(about = "Hash files with blake3, sha256 and more, or check a checksum manifest.")]
+    Hash(HashOpts),
+
+    #[command(about = "Chain compression, codecs, hashing, encryption and signing steps.")]
+    Pipe(PipeOpts),
 }
 
 fn verify_file(input: &str) -> Result<String, &'static str> {
@@ -64,6 +100,14 @@ fn verify_file(input: &str) -> Result<String, &'static str> {
     }
 }
 
+// a key file, or @name of a key in the keyring
+fn verify_key(input: &str) -> Result<String, &'static str> {
+    match input.starts_with('@') {
+        true => Ok(input.to_string()),
+        false => verify_file(input),
+    }
+}
+
 fn verify_path(input: &str) -> Result<PathBuf, &'static str> {
     let p: &Path = Path::new(input);
     if p.exists() && p.is_dir() {
@@ -73,6 +117,17 @@ fn verify_path(input: &str) -> Result<PathBuf, &'static str> {
     }
 }
 
+// files named on the command line are only replaced when asked to
+fn check_overwrite(path: &Path, force: bool) -> anyhow::Result<()> {
+    if !force && path.exists() {
+        anyhow::bail!(
+            "{} already exists, use --force to overwrite",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,215 @@
+use crate::{
+    hotp, hotp_verify, otp_secret_decode, otp_secret_gen, qr_render, totp_now, totp_verify_now,
+    CmdExecutor, OtpUri,
+};
+use anyhow::Result;
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum OtpSubCommand {
+    #[command(about = "Generate a new OTP secret and its otpauth:// provisioning URI")]
+    Generate(OtpGenerateOpts),
+
+    #[command(about = "Print the TOTP code for now, or the HOTP code for a counter")]
+    Code(OtpCodeOpts),
+
+    #[command(about = "Verify a TOTP/HOTP code within a drift window")]
+    Verify(OtpVerifyOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpGenerateOpts {
+    #[arg(long, default_value = "rcli")]
+    pub issuer: String,
+
+    #[arg(long)]
+    pub account: String,
+
+    #[arg(long, value_parser = parse_otp_alg, default_value = "sha1")]
+    pub alg: OtpAlg,
+
+    #[arg(long, value_parser = verify_otp_digits, default_value_t = 6)]
+    pub digits: u8,
+
+    #[arg(long, value_parser = verify_otp_period, default_value_t = 30)]
+    pub period: u64,
+
+    #[arg(
+        long,
+        help = "Initial HOTP counter, generates a TOTP secret if not set"
+    )]
+    pub counter: Option<u64>,
+
+    #[arg(long, default_value_t = false, help = "Render the URI as a QR code")]
+    pub qr: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpCodeOpts {
+    #[arg(short, long, help = "Base32 encoded secret")]
+    pub secret: String,
+
+    #[arg(long, value_parser = parse_otp_alg, default_value = "sha1")]
+    pub alg: OtpAlg,
+
+    #[arg(long, value_parser = verify_otp_digits, default_value_t = 6)]
+    pub digits: u8,
+
+    #[arg(long, value_parser = verify_otp_period, default_value_t = 30)]
+    pub period: u64,
+
+    #[arg(long, help = "HOTP counter, uses TOTP if not set")]
+    pub counter: Option<u64>,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpVerifyOpts {
+    #[arg(short, long, help = "Base32 encoded secret")]
+    pub secret: String,
+
+    #[arg(long)]
+    pub code: String,
+
+    #[arg(long, value_parser = parse_otp_alg, default_value = "sha1")]
+    pub alg: OtpAlg,
+
+    #[arg(long, value_parser = verify_otp_digits, default_value_t = 6)]
+    pub digits: u8,
+
+    #[arg(long, value_parser = verify_otp_period, default_value_t = 30)]
+    pub period: u64,
+
+    #[arg(long, help = "HOTP counter, uses TOTP if not set")]
+    pub counter: Option<u64>,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        help = "Steps accepted around now for TOTP, or ahead of the counter for HOTP"
+    )]
+    pub window: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OtpAlg {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+fn parse_otp_alg(s: &str) -> Result<OtpAlg, &'static str> {
+    s.parse()
+}
+
+fn verify_otp_digits(s: &str) -> Result<u8, &'static str> {
+    match s.parse() {
+        Ok(digits) if (6..=9).contains(&digits) => Ok(digits),
+        _ => Err("digits must be between 6 and 9"),
+    }
+}
+
+fn verify_otp_period(s: &str) -> Result<u64, &'static str> {
+    match s.parse() {
+        Ok(period) if period > 0 => Ok(period),
+        _ => Err("period must be a positive number of seconds"),
+    }
+}
+
+impl FromStr for OtpAlg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sha1" => Ok(OtpAlg::Sha1),
+            "sha256" => Ok(OtpAlg::Sha256),
+            "sha512" => Ok(OtpAlg::Sha512),
+            _ => Err("invalid OTP algorithm"),
+        }
+    }
+}
+
+impl Display for OtpAlg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpAlg::Sha1 => write!(f, "sha1"),
+            OtpAlg::Sha256 => write!(f, "sha256"),
+            OtpAlg::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+impl CmdExecutor for OtpGenerateOpts {
+    async fn execute(&self) -> Result<()> {
+        let secret = otp_secret_gen();
+        let uri = OtpUri {
+            secret: &secret,
+            issuer: &self.issuer,
+            account: &self.account,
+            alg: self.alg,
+            digits: self.digits,
+            period: self.period,
+            counter: self.counter,
+        }
+        .to_uri();
+        println!("{secret}");
+        println!("{uri}");
+        if self.qr {
+            println!("{}", qr_render(&uri)?);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for OtpCodeOpts {
+    async fn execute(&self) -> Result<()> {
+        let secret = otp_secret_decode(&self.secret)?;
+        let code = match self.counter {
+            Some(counter) => hotp(&secret, counter, self.alg, self.digits)?,
+            None => totp_now(&secret, self.period, self.alg, self.digits)?,
+        };
+        println!("{code}");
+        Ok(())
+    }
+}
+
+impl CmdExecutor for OtpVerifyOpts {
+    async fn execute(&self) -> Result<()> {
+        let secret = otp_secret_decode(&self.secret)?;
+        let valid = match self.counter {
+            Some(counter) => {
+                let matched = hotp_verify(
+                    &secret,
+                    &self.code,
+                    counter,
+                    self.window,
+                    self.alg,
+                    self.digits,
+                )?;
+                if let Some(c) = matched {
+                    eprintln!("matched counter={c}");
+                }
+                matched.is_some()
+            }
+            None => {
+                let matched = totp_verify_now(
+                    &secret,
+                    &self.code,
+                    self.period,
+                    self.window,
+                    self.alg,
+                    self.digits,
+                )?;
+                if let Some(offset) = matched {
+                    eprintln!("matched step offset={offset}");
+                }
+                matched.is_some()
+            }
+        };
+        println!("{valid}");
+        Ok(())
+    }
+}
@@ -1,8 +1,18 @@
-use crate::{gen_pass, CmdExecutor};
+use crate::{
+    derive_pass, gen_pass_batch, pass_hash, pass_hash_detect, pass_output, pass_verify,
+    read_secret, CmdExecutor, DerivePassRules,
+};
+use anyhow::Result;
 use clap::Parser;
+use enum_dispatch::enum_dispatch;
+use std::{fmt::Display, str::FromStr};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct PassOpts {
+    #[command(subcommand)]
+    pub cmd: Option<PassSubCommand>,
+
     #[arg(short, long, default_value_t = 12)]
     pub length: u8,
 
@@ -17,18 +27,217 @@ pub struct PassOpts {
 
     #[arg(long, default_value_t = false)]
     pub no_symbol: bool,
+
+    #[arg(short, long, default_value_t = 1)]
+    pub count: u32,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Make sure there are no duplicates in the batch"
+    )]
+    pub unique: bool,
+
+    #[arg(short, long, value_parser = parse_pass_output_format, default_value = "text")]
+    pub format: PassOutputFormat,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PassOutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+fn parse_pass_output_format(s: &str) -> Result<PassOutputFormat, &'static str> {
+    match s {
+        "text" => Ok(PassOutputFormat::Text),
+        "json" => Ok(PassOutputFormat::Json),
+        "csv" => Ok(PassOutputFormat::Csv),
+        _ => Err("invalid output format"),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExecutor)]
+pub enum PassSubCommand {
+    #[command(about = "Derive a site password from a master passphrase, nothing is stored")]
+    Derive(PassDeriveOpts),
+
+    #[command(about = "Hash a password read from a hidden prompt or stdin")]
+    Hash(PassHashOpts),
+
+    #[command(about = "Verify a password against a PHC or modular crypt hash")]
+    Verify(PassVerifyOpts),
+}
+
+#[derive(Parser, Debug)]
+pub struct PassDeriveOpts {
+    #[arg(long)]
+    pub site: String,
+
+    #[arg(long)]
+    pub login: String,
+
+    #[arg(long, default_value_t = 1)]
+    pub counter: u32,
+
+    #[arg(short, long, default_value_t = 16)]
+    pub length: u8,
+
+    #[arg(long, default_value_t = false)]
+    pub no_upper: bool,
+
+    #[arg(long, default_value_t = false)]
+    pub no_lower: bool,
+
+    #[arg(long, default_value_t = false)]
+    pub no_number: bool,
+
+    #[arg(long, default_value_t = false)]
+    pub no_symbol: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct PassHashOpts {
+    #[arg(short, long, value_parser = parse_pass_hash_alg, default_value = "argon2id")]
+    pub alg: PassHashAlg,
+
+    #[command(flatten)]
+    pub cost: PassHashCost,
+}
+
+#[derive(Parser, Debug)]
+pub struct PassHashCost {
+    #[arg(long, help = "argon2 memory cost in KiB")]
+    pub m_cost: Option<u32>,
+
+    #[arg(long, help = "argon2 number of iterations")]
+    pub t_cost: Option<u32>,
+
+    #[arg(long, help = "argon2 degree of parallelism")]
+    pub p_cost: Option<u32>,
+
+    #[arg(long, help = "bcrypt cost, 4 to 31")]
+    pub bcrypt_cost: Option<u32>,
+
+    #[arg(long, help = "scrypt log2 of the CPU/memory cost")]
+    pub log_n: Option<u8>,
+
+    #[arg(long, help = "scrypt block size")]
+    pub scrypt_r: Option<u32>,
+
+    #[arg(long, help = "scrypt parallelization")]
+    pub scrypt_p: Option<u32>,
+
+    #[arg(long, help = "pbkdf2 number of rounds")]
+    pub rounds: Option<u32>,
+}
+
+#[derive(Parser, Debug)]
+pub struct PassVerifyOpts {
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PassHashAlg {
+    Argon2id,
+    Argon2i,
+    Argon2d,
+    Bcrypt,
+    Scrypt,
+    Pbkdf2,
+}
+
+fn parse_pass_hash_alg(s: &str) -> Result<PassHashAlg, &'static str> {
+    s.parse()
+}
+
+impl FromStr for PassHashAlg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argon2id" => Ok(PassHashAlg::Argon2id),
+            "argon2i" => Ok(PassHashAlg::Argon2i),
+            "argon2d" => Ok(PassHashAlg::Argon2d),
+            "bcrypt" => Ok(PassHashAlg::Bcrypt),
+            "scrypt" => Ok(PassHashAlg::Scrypt),
+            "pbkdf2" => Ok(PassHashAlg::Pbkdf2),
+            _ => Err("invalid password hash algorithm"),
+        }
+    }
+}
+
+impl Display for PassHashAlg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassHashAlg::Argon2id => write!(f, "argon2id"),
+            PassHashAlg::Argon2i => write!(f, "argon2i"),
+            PassHashAlg::Argon2d => write!(f, "argon2d"),
+            PassHashAlg::Bcrypt => write!(f, "bcrypt"),
+            PassHashAlg::Scrypt => write!(f, "scrypt"),
+            PassHashAlg::Pbkdf2 => write!(f, "pbkdf2"),
+        }
+    }
 }
 
 impl CmdExecutor for PassOpts {
-    async fn execute(&self) -> anyhow::Result<()> {
-        let pass = gen_pass(
+    async fn execute(&self) -> Result<()> {
+        if let Some(cmd) = &self.cmd {
+            return cmd.execute().await;
+        }
+        let records = gen_pass_batch(
+            self.count,
+            self.unique,
             self.length,
             self.no_lower,
             self.no_upper,
             self.no_number,
             self.no_symbol,
         )?;
+        if let PassOutputFormat::Text = self.format {
+            for record in &records {
+                eprintln!("pass strength: {}", record.score);
+            }
+        }
+        println!("{}", pass_output(&records, self.format)?);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for PassDeriveOpts {
+    async fn execute(&self) -> Result<()> {
+        let master = read_secret("master passphrase: ")?;
+        let rules = DerivePassRules {
+            length: self.length,
+            no_lower: self.no_lower,
+            no_upper: self.no_upper,
+            no_number: self.no_number,
+            no_symbol: self.no_symbol,
+        };
+        let pass = derive_pass(&master, &self.site, &self.login, self.counter, &rules)?;
         println!("{pass}");
         Ok(())
     }
 }
+
+impl CmdExecutor for PassHashOpts {
+    async fn execute(&self) -> Result<()> {
+        let password = read_secret("password: ")?;
+        let hash = pass_hash(&password, self.alg, &self.cost)?;
+        println!("{hash}");
+        Ok(())
+    }
+}
+
+impl CmdExecutor for PassVerifyOpts {
+    async fn execute(&self) -> Result<()> {
+        let alg = pass_hash_detect(&self.hash)?;
+        eprintln!("algorithm: {alg}");
+        let password = read_secret("password: ")?;
+        let valid = pass_verify(&password, &self.hash)?;
+        println!("{valid}");
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use super::{check_overwrite, verify_file};
+use crate::{is_binary, pipe_run, CmdExecutor, Recipe};
+use anyhow::{bail, Result};
+use clap::Parser;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::PathBuf,
+};
+
+#[derive(Debug, Parser)]
+pub struct PipeOpts {
+    #[arg(
+        help = "Steps separated by |, e.g. \"gunzip | b64:url-decode | chacha:decrypt(key=k.txt) | hex\", double quote values with ,|()# in them",
+        required_unless_present = "recipe"
+    )]
+    pub steps: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        conflicts_with = "steps",
+        help = "Replay the steps saved in a recipe file"
+    )]
+    pub recipe: Option<String>,
+
+    #[arg(long, help = "Save the steps to a recipe file")]
+    pub save: Option<PathBuf>,
+
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, help = "Write the result to a file instead of stdout")]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Overwrite --output and --save, or write binary data to a terminal"
+    )]
+    pub force: bool,
+}
+
+impl CmdExecutor for PipeOpts {
+    async fn execute(&self) -> Result<()> {
+        let recipe: Recipe = match (&self.steps, &self.recipe) {
+            (Some(steps), _) => steps.parse()?,
+            (None, Some(path)) => fs::read_to_string(path)?.parse()?,
+            (None, None) => unreachable!("clap requires steps or a recipe"),
+        };
+        // both are checked before either is written
+        for path in self.save.iter().chain(&self.output) {
+            check_overwrite(path, self.force)?;
+        }
+        if let Some(path) = &self.save {
+            fs::write(path, format!("{recipe}\n"))?;
+        }
+        // stages run on their own threads, so the input must be sendable
+        let mut reader: Box<dyn Read + Send> = match self.input.as_str() {
+            "-" => Box::new(io::stdin()),
+            path => Box::new(File::open(path)?),
+        };
+        if let Some(output) = &self.output {
+            let mut file = BufWriter::new(File::create(output)?);
+            pipe_run(&recipe, &mut reader, &mut file)?;
+            file.flush()?;
+            return Ok(());
+        }
+        let mut stdout = io::stdout().lock();
+        if !stdout.is_terminal() {
+            let mut stdout = BufWriter::new(stdout);
+            pipe_run(&recipe, &mut reader, &mut stdout)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+        let mut output = Vec::new();
+        pipe_run(&recipe, &mut reader, &mut output)?;
+        if !self.force && is_binary(&output) {
+            bail!("refusing to write binary data to a terminal, use --output or --force");
+        }
+        stdout.write_all(&output)?;
+        if !output.ends_with(b"\n") {
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,24 @@
-use super::{verify_file, verify_path};
+use super::{parse_key_format, verify_file, verify_key, verify_path, KeyFormat};
 use crate::{
-    base64_encode, data_decrypt, data_encrypt, get_reader, key_gen, text_sign, text_verify,
-    CmdExecutor,
+    age_decrypt, age_encrypt, base64_decode, blake3_key_load, chacha_open, get_reader, is_age,
+    is_stream, key_gen, keyring_resolve, minisign_trusted_comment, read_new_passphrase,
+    sig_file_create, sig_file_read, sig_file_verify, sig_file_write, ssh_sign_file,
+    ssh_verify_file, stream_decrypt, stream_encrypt, text_sign_file, text_verify_file, AgeIdentity,
+    AgeRecipient, CmdExecutor, KeyKind, Keyring, SealedBox, X25519Identity, X25519Recipient,
+    AGE_SCRYPT_WORK_FACTOR, PASSPHRASE_ENV, SSHSIG_DEFAULT_NAMESPACE,
 };
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
-use std::{fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use time::format_description::well_known::Rfc3339;
+use zeroize::Zeroizing;
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
@@ -17,13 +29,13 @@ pub enum TextSubCommand {
     #[command(about = "Verify a signature with a public/session key")]
     Verify(TextVerifyOpts),
 
-    #[command(about = "Generate a random blake3 key or ed25519 key pair")]
+    #[command(about = "Generate a blake3 key, or an ed25519, minisign, signify or ssh key pair")]
     Generate(KeyGenerateOpts),
 
-    #[command(about = "Chacha20 encrypt a text with a key")]
+    #[command(about = "Chacha20 encrypt a text with a key, or to X25519 recipients")]
     Encrypt(TextEncryptOpts),
 
-    #[command(about = "Chacha20 decrypt a text with a key")]
+    #[command(about = "Chacha20 decrypt a text with a key, or a sealed box with your private key")]
     Decrypt(TextDecryptOpts),
 }
 
@@ -32,8 +44,47 @@ pub struct TextEncryptOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    #[arg(short, long, value_parser = verify_chacha_key)]
-    pub key: String,
+    #[arg(
+        long,
+        value_parser = parse_encrypt_format,
+        default_value = "rcli",
+        help = "rcli, a chunked chacha20 stream or a sealed box, or age"
+    )]
+    pub format: EncryptFormat,
+
+    #[arg(
+        short,
+        long,
+        value_parser = verify_chacha_key,
+        required_unless_present_any = ["recipient", "passphrase"],
+        conflicts_with_all = ["recipient", "passphrase"],
+        help = "A 32 byte key, or @name of a blake3 key in the keyring"
+    )]
+    pub key: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Ed25519 public key file, @name or x25519 public key in hex to encrypt to, or age1... for age, repeat for more"
+    )]
+    pub recipient: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        conflicts_with = "recipient",
+        help = "Encrypt with a passphrase, age only"
+    )]
+    pub passphrase: bool,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "Write base64 ASCII armor instead of binary"
+    )]
+    pub armor: bool,
 }
 
 #[derive(Debug, Parser)]
@@ -41,17 +92,46 @@ pub struct TextDecryptOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    #[arg(short, long, value_parser = verify_chacha_key)]
-    pub key: String,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_chacha_key,
+        conflicts_with = "identity",
+        help = "A 32 byte key, or @name of a blake3 key in the keyring"
+    )]
+    pub key: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = verify_key,
+        help = "Ed25519 private key file, @name or age identity file to open a sealed box or age file with, repeat for more"
+    )]
+    pub identity: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptFormat {
+    Rcli,
+    Age,
 }
 
 fn verify_chacha_key(s: &str) -> Result<String, &'static str> {
-    if s.len() != 32 {
+    if !s.starts_with('@') && s.len() != 32 {
         return Err("key length must be 32 bytes");
     }
     Ok(s.to_string())
 }
 
+// the key itself, or the bytes of a blake3 key in the keyring
+fn chacha_key(key: &str) -> Result<Zeroizing<Vec<u8>>> {
+    match key.starts_with('@') {
+        true => Ok(Zeroizing::new(
+            blake3_key_load(&keyring_resolve(key, KeyKind::Blake3)?)?.to_vec(),
+        )),
+        false => Ok(Zeroizing::new(key.as_bytes().to_vec())),
+    }
+}
+
 #[derive(Debug, Parser)]
 pub struct TextSignOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
@@ -60,8 +140,42 @@ pub struct TextSignOpts {
     #[arg(short, long, value_parser = parse_format, default_value = "blake3")]
     pub format: TextSignFormat,
 
-    #[arg(short, long, value_parser = verify_file)]
+    #[arg(
+        short,
+        long,
+        value_parser = verify_key,
+        help = "Key file, or @name or @fingerprint of a key in the keyring"
+    )]
     pub key: String,
+
+    #[arg(
+        long,
+        value_parser = parse_sign_mode,
+        default_value = "pure",
+        help = "How ed25519 signs: pure buffers the whole input, ph (Ed25519ph) and blake3 sign a streamed digest"
+    )]
+    pub mode: SignMode,
+
+    #[arg(
+        long,
+        help = "Write a signature file with the key fingerprint, time and file details"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "out",
+        help = "Comment to record in the signature file, the trusted comment for minisign"
+    )]
+    pub comment: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = SSHSIG_DEFAULT_NAMESPACE,
+        help = "What an ssh signature is for, it only verifies in the same namespace"
+    )]
+    pub namespace: String,
 }
 
 #[derive(Debug, Parser)]
@@ -69,14 +183,36 @@ pub struct TextVerifyOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
-    #[arg(long, value_parser = parse_format, default_value = "blake3")]
-    pub format: TextSignFormat,
+    #[arg(
+        long,
+        value_parser = parse_format,
+        help = "Format of a bare signature, blake3 if not given. Signature files record their own, minisign, signify and ssh need it"
+    )]
+    pub format: Option<TextSignFormat>,
 
-    #[arg(short, long, value_parser = verify_file)]
+    #[arg(
+        short,
+        long,
+        value_parser = verify_key,
+        help = "Key file, or @name or @fingerprint of a key in the keyring"
+    )]
     pub key: String,
 
-    #[arg(long)]
-    pub sig: String,
+    #[arg(
+        long,
+        help = "A bare signature or a signature file, defaults to <input>.sig (<input>.minisig for minisign)"
+    )]
+    pub sig: Option<String>,
+
+    #[arg(short, long, default_value = SSHSIG_DEFAULT_NAMESPACE, help = "Namespace of an ssh signature")]
+    pub namespace: String,
+
+    #[arg(
+        short = 'I',
+        long,
+        help = "Principal an ssh signature must be from, any in the allowed signers file if not given"
+    )]
+    pub identity: Option<String>,
 }
 
 #[derive(Debug, Parser)]
@@ -86,18 +222,133 @@ pub struct KeyGenerateOpts {
 
     #[arg(short, long, value_parser = verify_path)]
     pub output_path: PathBuf,
+
+    #[arg(
+        long,
+        value_parser = parse_key_format,
+        default_value = "raw",
+        help = "Key file format of blake3 and ed25519 keys: raw, pem, openssh or jwk"
+    )]
+    pub key_format: KeyFormat,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Seal the secret key in a passphrase protected keystore"
+    )]
+    pub encrypt: bool,
 }
 
 #[derive(Debug, Parser, Clone, Copy)]
 pub enum TextSignFormat {
     Blake3,
     Ed25519,
+    Minisign,
+    Signify,
+    Ssh,
+}
+
+impl TextSignFormat {
+    // minisign, signify and ssh have their own key and signature files
+    pub fn is_native(&self) -> bool {
+        matches!(
+            self,
+            TextSignFormat::Minisign | TextSignFormat::Signify | TextSignFormat::Ssh
+        )
+    }
+
+    // which half of a key pair in the keyring signs or verifies
+    pub fn key_kind(&self, sign: bool) -> KeyKind {
+        match (self, sign) {
+            (TextSignFormat::Blake3, _) => KeyKind::Blake3,
+            (_, true) => KeyKind::Ed25519Sign,
+            (_, false) => KeyKind::Ed25519Verify,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SignMode {
+    Pure,
+    Prehash,
+    Blake3,
+}
+
+impl SignMode {
+    pub fn sig_prefix(&self) -> &'static str {
+        match self {
+            SignMode::Pure => "ed25519",
+            SignMode::Prehash => "ed25519ph",
+            SignMode::Blake3 => "ed25519b3",
+        }
+    }
+
+    pub fn from_sig_prefix(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "ed25519" => Ok(SignMode::Pure),
+            "ed25519ph" => Ok(SignMode::Prehash),
+            "ed25519b3" => Ok(SignMode::Blake3),
+            _ => anyhow::bail!("unknown signature mode {:?}", s),
+        }
+    }
+}
+
+fn parse_sign_mode(s: &str) -> Result<SignMode, &'static str> {
+    s.parse()
+}
+
+impl FromStr for SignMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pure" => Ok(SignMode::Pure),
+            "ph" => Ok(SignMode::Prehash),
+            "blake3" => Ok(SignMode::Blake3),
+            _ => Err("invalid sign mode, expect one of pure, ph, blake3"),
+        }
+    }
+}
+
+impl Display for SignMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignMode::Pure => write!(f, "pure"),
+            SignMode::Prehash => write!(f, "ph"),
+            SignMode::Blake3 => write!(f, "blake3"),
+        }
+    }
 }
 
 fn parse_format(s: &str) -> Result<TextSignFormat, &'static str> {
     s.parse()
 }
 
+fn parse_encrypt_format(s: &str) -> Result<EncryptFormat, &'static str> {
+    s.parse()
+}
+
+impl FromStr for EncryptFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rcli" => Ok(EncryptFormat::Rcli),
+            "age" => Ok(EncryptFormat::Age),
+            _ => Err("invalid encrypt format, expect one of rcli, age"),
+        }
+    }
+}
+
+impl Display for EncryptFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptFormat::Rcli => write!(f, "rcli"),
+            EncryptFormat::Age => write!(f, "age"),
+        }
+    }
+}
+
 impl FromStr for TextSignFormat {
     type Err = &'static str;
 
@@ -105,6 +356,9 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
+            "minisign" => Ok(TextSignFormat::Minisign),
+            "signify" => Ok(TextSignFormat::Signify),
+            "ssh" => Ok(TextSignFormat::Ssh),
             _ => Err("invalid format"),
         }
     }
@@ -115,50 +369,272 @@ impl Display for TextSignFormat {
         match self {
             TextSignFormat::Blake3 => write!(f, "blake3"),
             TextSignFormat::Ed25519 => write!(f, "ed25519"),
+            TextSignFormat::Minisign => write!(f, "minisign"),
+            TextSignFormat::Signify => write!(f, "signify"),
+            TextSignFormat::Ssh => write!(f, "ssh"),
         }
     }
 }
 
 impl CmdExecutor for TextSignOpts {
     async fn execute(&self) -> Result<()> {
-        let mut reader = get_reader(&self.input)?;
-        let encoded = text_sign(self.format, &mut reader, &self.key)?;
+        let key = &keyring_resolve(&self.key, self.format.key_kind(true))?;
+        if self.format.is_native() {
+            let comment = self.comment.as_deref();
+            let sig = match self.format {
+                TextSignFormat::Ssh if comment.is_some() => bail!("ssh signatures have no comment"),
+                TextSignFormat::Ssh => ssh_sign_file(&self.input, key, &self.namespace)?,
+                _ => text_sign_file(self.format, self.mode, &self.input, key, comment)?,
+            };
+            match &self.out {
+                Some(out) => fs::write(out, sig)?,
+                None => print!("{sig}"),
+            }
+            return Ok(());
+        }
+        if let Some(out) = &self.out {
+            let sig = sig_file_create(
+                self.format,
+                self.mode,
+                &self.input,
+                key,
+                self.comment.clone(),
+            )?;
+            sig_file_write(&sig, out)?;
+            eprintln!(
+                "signed with key {}, signature written to {}",
+                sig.key_fingerprint,
+                out.display()
+            );
+            return Ok(());
+        }
+        let encoded = text_sign_file(self.format, self.mode, &self.input, key, None)?;
         println!("{encoded}");
         Ok(())
     }
 }
 
+fn verify_native(format: TextSignFormat, input: &str, key: &str, sig: Option<&str>) -> Result<()> {
+    let ext = match format {
+        TextSignFormat::Minisign => "minisig",
+        _ => "sig",
+    };
+    let sig = match sig {
+        Some(sig) => sig.to_string(),
+        None if input != "-" => format!("{input}.{ext}"),
+        None => bail!("no signature given, use --sig"),
+    };
+    let content = fs::read_to_string(&sig)?;
+    if !text_verify_file(format, input, key, &content)? {
+        bail!("BAD {} signature in {}", format, sig);
+    }
+    match minisign_trusted_comment(&content) {
+        Some(comment) => {
+            println!("Signature and comment signature verified\nTrusted comment: {comment}")
+        }
+        None => println!("Signature Verified"),
+    }
+    Ok(())
+}
+
+// the key is an allowed_signers file, or the signer's public key
+fn verify_ssh(opts: &TextVerifyOpts, key: &str) -> Result<()> {
+    let sig = match &opts.sig {
+        Some(sig) => sig.clone(),
+        None if opts.input != "-" => format!("{}.sig", opts.input),
+        None => bail!("no signature given, use --sig"),
+    };
+    let content = fs::read_to_string(&sig)?;
+    let identity = opts.identity.clone();
+    let Some(check) = ssh_verify_file(&opts.input, key, &content, &opts.namespace, identity)?
+    else {
+        bail!("BAD ssh signature in {}", sig);
+    };
+    println!(
+        "Good \"{}\" signature for {} with ED25519 key {}",
+        opts.namespace, check.principal, check.fingerprint
+    );
+    Ok(())
+}
+
 impl CmdExecutor for TextVerifyOpts {
     async fn execute(&self) -> Result<()> {
-        let mut reader = get_reader(&self.input)?;
-        let valid = text_verify(self.format, &mut reader, &self.key, &self.sig)?;
-        println!("{valid}");
+        // without a format a blake3 key is still found, only a key pair shares a fingerprint
+        let kind = self
+            .format
+            .map_or(KeyKind::Ed25519Verify, |f| f.key_kind(false));
+        let key = &keyring_resolve(&self.key, kind)?;
+        if let Some(TextSignFormat::Ssh) = self.format {
+            return verify_ssh(self, key);
+        }
+        if let Some(format) = self.format.filter(|f| f.is_native()) {
+            return verify_native(format, &self.input, key, self.sig.as_deref());
+        }
+        let sig = match &self.sig {
+            Some(sig) => sig.clone(),
+            None if self.input != "-" => format!("{}.sig", self.input),
+            None => bail!("no signature given, use --sig"),
+        };
+        // only an explicit --sig can be a bare signature rather than a file
+        if !Path::new(&sig).is_file() {
+            if self.sig.is_none() {
+                bail!("signature file {} not found", sig);
+            }
+            let format = self.format.unwrap_or(TextSignFormat::Blake3);
+            let valid = text_verify_file(format, &self.input, key, &sig)?;
+            println!("{valid}");
+            return Ok(());
+        }
+        let sig_file = sig_file_read(Path::new(&sig))?;
+        if let Some(format) = self.format {
+            if !sig_file.algorithm.starts_with(&format.to_string()) {
+                bail!(
+                    "{} holds a {} signature, not {}",
+                    sig,
+                    sig_file.algorithm,
+                    format
+                );
+            }
+        }
+        let name = sig_file.file_name.as_deref().unwrap_or("stdin");
+        if !sig_file_verify(&sig_file, &self.input, key)? {
+            bail!("BAD {} signature for {}", sig_file.algorithm, name);
+        }
+        println!(
+            "Good {} signature for {} ({} bytes) by key {}, signed {}",
+            sig_file.algorithm,
+            name,
+            sig_file.file_size,
+            sig_file.key_fingerprint,
+            sig_file.created.format(&Rfc3339)?
+        );
+        if let Some(comment) = &sig_file.comment {
+            println!("comment: {comment}");
+        }
+        // what the keyring knows about the signer, if it has the key
+        if let Some(entry) = Keyring::open()
+            .ok()
+            .as_ref()
+            .and_then(|k| k.find_fingerprint(&sig_file.key_fingerprint))
+        {
+            match &entry.comment {
+                Some(comment) => println!(
+                    "keyring: {}, trust {}, {}",
+                    entry.name, entry.trust, comment
+                ),
+                None => println!("keyring: {}, trust {}", entry.name, entry.trust),
+            }
+        }
         Ok(())
     }
 }
 
 impl CmdExecutor for KeyGenerateOpts {
     async fn execute(&self) -> Result<()> {
-        key_gen(self.format, &self.output_path)
+        key_gen(
+            self.format,
+            &self.output_path,
+            self.key_format,
+            self.encrypt,
+        )
     }
 }
 
 impl CmdExecutor for TextEncryptOpts {
     async fn execute(&self) -> Result<()> {
         let mut data_reader = get_reader(&self.input)?;
-        let encrypted = data_encrypt(&mut data_reader, &self.key)?;
-        let b64 = base64_encode(encrypted);
-        println!("{b64}");
-        Ok(())
+        if self.format == EncryptFormat::Age {
+            return encrypt_age(self, &mut data_reader);
+        }
+        if self.passphrase {
+            bail!("--passphrase is for --format age");
+        }
+        let Some(key) = &self.key else {
+            if self.armor {
+                bail!("a sealed box is already text, --armor is for a key or --format age");
+            }
+            let recipients = self
+                .recipient
+                .iter()
+                .map(|r| X25519Recipient::load(r))
+                .collect::<Result<Vec<_>>>()?;
+            let mut data = Vec::new();
+            data_reader.read_to_end(&mut data)?;
+            let sealed = SealedBox::seal(&data, &recipients)?;
+            println!("{}", serde_json::to_string_pretty(&sealed)?);
+            return Ok(());
+        };
+        let mut stdout = binary_stdout(self.armor)?;
+        stream_encrypt(&mut data_reader, &mut stdout, &chacha_key(key)?, self.armor)
+    }
+}
+
+fn binary_stdout(armor: bool) -> Result<io::StdoutLock<'static>> {
+    let stdout = io::stdout().lock();
+    if !armor && stdout.is_terminal() {
+        bail!("refusing to write binary output to a terminal, use --armor");
     }
+    Ok(stdout)
+}
+
+fn encrypt_age(opts: &TextEncryptOpts, reader: &mut dyn Read) -> Result<()> {
+    if opts.key.is_some() {
+        bail!("age encrypts to recipients or a passphrase, not a shared key");
+    }
+    let recipients = match opts.passphrase {
+        true => {
+            let passphrase = read_new_passphrase("Passphrase: ", PASSPHRASE_ENV)?;
+            vec![AgeRecipient::Scrypt(passphrase, AGE_SCRYPT_WORK_FACTOR)]
+        }
+        false => opts
+            .recipient
+            .iter()
+            .map(|r| AgeRecipient::parse(r))
+            .collect::<Result<_>>()?,
+    };
+    let mut stdout = binary_stdout(opts.armor)?;
+    age_encrypt(reader, &mut stdout, &recipients, opts.armor)
 }
 
 impl CmdExecutor for TextDecryptOpts {
     async fn execute(&self) -> Result<()> {
-        let mut data_reader = get_reader(&self.input)?;
-        let decrypted = data_decrypt(&mut data_reader, &self.key)?;
-        let decrypted = String::from_utf8(decrypted)?;
-        println!("{decrypted}");
+        let data_reader = get_reader(&self.input)?;
+        let Some(key) = &self.key else {
+            let mut reader = BufReader::new(data_reader);
+            if is_age(reader.fill_buf()?) {
+                let mut identities = Vec::new();
+                for identity in &self.identity {
+                    identities.extend(AgeIdentity::load(identity)?);
+                }
+                return age_decrypt(&mut reader, &mut io::stdout().lock(), &identities);
+            }
+            if self.identity.is_empty() {
+                bail!("give --key for a chacha20 text, or --identity for a sealed box");
+            }
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            let sealed = SealedBox::parse(&content)?;
+            let identities = self
+                .identity
+                .iter()
+                .map(|identity| X25519Identity::load(identity))
+                .collect::<Result<Vec<_>>>()?;
+            let decrypted = identities
+                .iter()
+                .find_map(|identity| sealed.open(identity).ok())
+                .ok_or_else(|| anyhow!("not encrypted to any of the identities"))?;
+            io::stdout().write_all(&decrypted)?;
+            return Ok(());
+        };
+        let mut reader = BufReader::new(data_reader);
+        if is_stream(reader.fill_buf()?) {
+            return stream_decrypt(&mut reader, &mut io::stdout().lock(), &chacha_key(key)?);
+        }
+        // base64 text from before the stream format
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        let decrypted = chacha_open(&base64_decode(content.trim())?, &chacha_key(key)?)?;
+        io::stdout().write_all(&decrypted)?;
         Ok(())
     }
 }
@@ -0,0 +1,471 @@
+use crate::{
+    is_armored, read_passphrase, stream_open, stream_seal, ArmorReader, ArmorWriter,
+    X25519Identity, X25519Recipient, PASSPHRASE_ENV, STREAM_CHUNK_SIZE,
+};
+use anyhow::{anyhow, bail, Result};
+use bech32::{FromBase32, ToBase32, Variant};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use data_encoding::BASE64_NOPAD;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use zeroize::Zeroizing;
+
+// https://age-encryption.org/v1
+const AGE_INTRO: &str = "age-encryption.org/v1";
+const AGE_X25519_INFO: &[u8] = b"age-encryption.org/v1/X25519";
+const AGE_SCRYPT_SALT: &[u8] = b"age-encryption.org/v1/scrypt";
+const AGE_RECIPIENT_HRP: &str = "age";
+const AGE_IDENTITY_HRP: &str = "age-secret-key-";
+const AGE_ARMOR_LABEL: &str = "AGE ENCRYPTED FILE";
+const AGE_COLUMNS: usize = 64;
+// what age itself uses, about a second to derive
+pub const AGE_SCRYPT_WORK_FACTOR: u8 = 18;
+// refuse files that would take minutes and gigabytes to open
+const AGE_SCRYPT_MAX_WORK_FACTOR: u8 = 22;
+
+pub enum AgeRecipient {
+    X25519(X25519Recipient),
+    Scrypt(Zeroizing<String>, u8),
+}
+
+pub enum AgeIdentity {
+    X25519(X25519Identity),
+    Scrypt(Zeroizing<String>),
+}
+
+struct AgeStanza {
+    tag: String,
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+impl AgeRecipient {
+    // age1... or anything X25519Recipient::load takes, an ed25519 key or @name
+    pub fn parse(recipient: &str) -> Result<Self> {
+        if !recipient.starts_with("age1") {
+            return Ok(Self::X25519(X25519Recipient::load(recipient)?));
+        }
+        let key = bech32_decode(recipient, AGE_RECIPIENT_HRP)?;
+        Ok(Self::X25519(X25519Recipient::from_bytes(*key)))
+    }
+
+    fn wrap(&self, file_key: &[u8; 16]) -> Result<AgeStanza> {
+        match self {
+            AgeRecipient::X25519(recipient) => {
+                let ephemeral = X25519Identity::generate();
+                let share = ephemeral.recipient().to_bytes();
+                let shared = ephemeral.shared(&recipient.to_bytes())?;
+                let salt = [share, recipient.to_bytes()].concat();
+                let key = hkdf_sha256(&salt, shared.as_ref(), AGE_X25519_INFO)?;
+                Ok(AgeStanza {
+                    tag: "X25519".into(),
+                    args: vec![BASE64_NOPAD.encode(&share)],
+                    body: aead_wrap(&key, file_key)?,
+                })
+            }
+            AgeRecipient::Scrypt(passphrase, work_factor) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                let key = age_scrypt(passphrase, &salt, *work_factor)?;
+                Ok(AgeStanza {
+                    tag: "scrypt".into(),
+                    args: vec![BASE64_NOPAD.encode(&salt), work_factor.to_string()],
+                    body: aead_wrap(&key, file_key)?,
+                })
+            }
+        }
+    }
+}
+
+impl AgeIdentity {
+    // a file of AGE-SECRET-KEY-1... lines as age-keygen writes, or an ed25519 key or @name
+    pub fn load(path: &str) -> Result<Vec<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            // a keyring name, or a binary ed25519 key
+            Err(_) if path.starts_with('@') => String::new(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => String::new(),
+            Err(e) => bail!("failed to read identity {}: {}", path, e),
+        };
+        let keys = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("AGE-SECRET-KEY-1"))
+            .map(|line| {
+                let key = bech32_decode(line, AGE_IDENTITY_HRP)?;
+                Ok(Self::X25519(X25519Identity::from_bytes(*key)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match keys.is_empty() {
+            true => Ok(vec![Self::X25519(X25519Identity::load(path)?)]),
+            false => Ok(keys),
+        }
+    }
+
+    // none when the stanza is of another type or for another key
+    fn unwrap(&self, stanza: &AgeStanza) -> Result<Option<Zeroizing<[u8; 16]>>> {
+        match (self, stanza.tag.as_str()) {
+            (AgeIdentity::X25519(identity), "X25519") => {
+                let [share] = stanza.args.as_slice() else {
+                    bail!("malformed X25519 stanza");
+                };
+                let share: [u8; 32] = BASE64_NOPAD
+                    .decode(share.as_bytes())?
+                    .try_into()
+                    .map_err(|_| anyhow!("malformed X25519 stanza"))?;
+                if stanza.body.len() != 32 {
+                    bail!("malformed X25519 stanza");
+                }
+                let shared = identity.shared(&share)?;
+                let salt = [share, identity.recipient().to_bytes()].concat();
+                let key = hkdf_sha256(&salt, shared.as_ref(), AGE_X25519_INFO)?;
+                Ok(aead_unwrap(&key, &stanza.body))
+            }
+            (AgeIdentity::Scrypt(passphrase), "scrypt") => {
+                let [salt, work_factor] = stanza.args.as_slice() else {
+                    bail!("malformed scrypt stanza");
+                };
+                let salt = BASE64_NOPAD.decode(salt.as_bytes())?;
+                let canonical = work_factor.bytes().all(|b| b.is_ascii_digit())
+                    && !work_factor.starts_with('0');
+                let work_factor: u8 = match work_factor.parse() {
+                    Ok(w) if salt.len() == 16 && canonical => w,
+                    _ => bail!("malformed scrypt stanza"),
+                };
+                if work_factor > AGE_SCRYPT_MAX_WORK_FACTOR {
+                    bail!("scrypt work factor {} is too large", work_factor);
+                }
+                let key = age_scrypt(passphrase, &salt, work_factor)?;
+                match aead_unwrap(&key, &stanza.body) {
+                    Some(file_key) => Ok(Some(file_key)),
+                    None => bail!("wrong passphrase"),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl AgeStanza {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"->");
+        for arg in std::iter::once(&self.tag).chain(&self.args) {
+            out.push(b' ');
+            out.extend_from_slice(arg.as_bytes());
+        }
+        out.push(b'\n');
+        let body = BASE64_NOPAD.encode(&self.body);
+        // the last line is always short, empty if need be, that is how it ends
+        for line in body.as_bytes().chunks(AGE_COLUMNS) {
+            out.extend_from_slice(line);
+            out.push(b'\n');
+        }
+        if body.len().is_multiple_of(AGE_COLUMNS) {
+            out.push(b'\n');
+        }
+    }
+}
+
+pub fn age_recipient_encode(recipient: &X25519Recipient) -> Result<String> {
+    Ok(bech32::encode(
+        AGE_RECIPIENT_HRP,
+        recipient.to_bytes().to_base32(),
+        Variant::Bech32,
+    )?)
+}
+
+pub fn age_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    recipients: &[AgeRecipient],
+    armor: bool,
+) -> Result<()> {
+    if recipients.is_empty() {
+        bail!("no recipients to encrypt to");
+    }
+    if recipients.len() > 1
+        && recipients
+            .iter()
+            .any(|r| matches!(r, AgeRecipient::Scrypt(..)))
+    {
+        bail!("a passphrase can't be combined with other recipients");
+    }
+    let mut file_key = Zeroizing::new([0u8; 16]);
+    OsRng.fill_bytes(file_key.as_mut());
+    let mut header = format!("{AGE_INTRO}\n").into_bytes();
+    for recipient in recipients {
+        recipient.wrap(&file_key)?.write(&mut header);
+    }
+    header.extend_from_slice(b"---");
+    let mac = header_mac(&file_key, &header)?.finalize().into_bytes();
+    header.extend_from_slice(format!(" {}\n", BASE64_NOPAD.encode(&mac)).as_bytes());
+
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let key = hkdf_sha256(&nonce, file_key.as_ref(), b"payload")?;
+    let mut reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, reader);
+    match armor {
+        true => {
+            let mut armored = ArmorWriter::new(writer, AGE_ARMOR_LABEL)?;
+            armored.write_all(&header)?;
+            armored.write_all(&nonce)?;
+            stream_seal(&key, &mut reader, &mut armored)?;
+            armored.finish()
+        }
+        false => {
+            writer.write_all(&header)?;
+            writer.write_all(&nonce)?;
+            stream_seal(&key, &mut reader, writer)
+        }
+    }
+}
+
+pub fn age_decrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    identities: &[AgeIdentity],
+) -> Result<()> {
+    let mut reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, reader);
+    if is_armored(reader.fill_buf()?, AGE_ARMOR_LABEL) {
+        let mut armored = ArmorReader::new(&mut reader, AGE_ARMOR_LABEL)?;
+        return age_decrypt_binary(
+            &mut BufReader::with_capacity(STREAM_CHUNK_SIZE, &mut armored),
+            writer,
+            identities,
+        );
+    }
+    age_decrypt_binary(&mut reader, writer, identities)
+}
+
+pub fn is_age(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(AGE_INTRO.as_bytes()) || is_armored(data, AGE_ARMOR_LABEL)
+}
+
+fn age_decrypt_binary(
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+    identities: &[AgeIdentity],
+) -> Result<()> {
+    let (stanzas, header, mac) = age_header_read(reader)?;
+    let has_scrypt = stanzas.iter().any(|s| s.tag == "scrypt");
+    if has_scrypt && stanzas.len() > 1 {
+        bail!("an scrypt stanza must be the only one in the file");
+    }
+    // a passphrase protected file asks for the passphrase
+    let prompted;
+    let identities = match has_scrypt
+        && !identities
+            .iter()
+            .any(|i| matches!(i, AgeIdentity::Scrypt(_)))
+    {
+        true => {
+            prompted = [AgeIdentity::Scrypt(read_passphrase(
+                "Passphrase: ",
+                PASSPHRASE_ENV,
+            )?)];
+            &prompted[..]
+        }
+        false => identities,
+    };
+    let mut file_key = None;
+    'outer: for identity in identities {
+        for stanza in &stanzas {
+            if let Some(key) = identity.unwrap(stanza)? {
+                file_key = Some(key);
+                break 'outer;
+            }
+        }
+    }
+    let file_key = file_key.ok_or_else(|| anyhow!("no identity matched any of the recipients"))?;
+    header_mac(&file_key, &header)?
+        .verify_slice(&mac)
+        .map_err(|_| anyhow!("header MAC doesn't match, the file was tampered with"))?;
+    let mut nonce = [0u8; 16];
+    reader
+        .read_exact(&mut nonce)
+        .map_err(|_| anyhow!("file is truncated before the payload"))?;
+    let key = hkdf_sha256(&nonce, file_key.as_ref(), b"payload")?;
+    stream_open(&key, reader, writer)
+}
+
+// the stanzas, the header bytes the MAC covers, and the MAC
+fn age_header_read(reader: &mut dyn BufRead) -> Result<(Vec<AgeStanza>, Vec<u8>, Vec<u8>)> {
+    let mut header = Vec::new();
+    let mut line = Vec::new();
+    let mut read_line = |header: &mut Vec<u8>, line: &mut Vec<u8>| -> Result<()> {
+        line.clear();
+        reader.read_until(b'\n', line)?;
+        if line.pop() != Some(b'\n') {
+            bail!("not an age file, or its header is truncated");
+        }
+        header.extend_from_slice(line);
+        header.push(b'\n');
+        Ok(())
+    };
+    read_line(&mut header, &mut line)?;
+    if line != AGE_INTRO.as_bytes() {
+        bail!("not an age v1 file");
+    }
+    let mut stanzas = Vec::new();
+    loop {
+        read_line(&mut header, &mut line)?;
+        let text = String::from_utf8(line.clone())?;
+        if let Some(mac) = text.strip_prefix("--- ") {
+            // the MAC covers the header up to and including "---"
+            header.truncate(header.len() - mac.len() - 2);
+            let mac = BASE64_NOPAD.decode(mac.as_bytes())?;
+            return Ok((stanzas, header, mac));
+        }
+        let Some(args) = text.strip_prefix("-> ") else {
+            bail!("malformed age header line {:?}", text);
+        };
+        // the type and arguments are non-empty runs of printable ASCII
+        let args: Vec<String> = args.split(' ').map(String::from).collect();
+        if args
+            .iter()
+            .any(|arg| arg.is_empty() || !arg.bytes().all(|b| b.is_ascii_graphic()))
+        {
+            bail!("malformed stanza line {:?}", text);
+        }
+        let mut args = args.into_iter();
+        let tag = args.next().expect("split yields at least one part");
+        let mut body = String::new();
+        loop {
+            read_line(&mut header, &mut line)?;
+            if line.len() > AGE_COLUMNS {
+                bail!("stanza body line is longer than {} columns", AGE_COLUMNS);
+            }
+            body.push_str(std::str::from_utf8(&line)?);
+            if line.len() < AGE_COLUMNS {
+                break;
+            }
+        }
+        stanzas.push(AgeStanza {
+            tag,
+            args: args.collect(),
+            body: BASE64_NOPAD.decode(body.as_bytes())?,
+        });
+    }
+}
+
+fn header_mac(file_key: &[u8; 16], header: &[u8]) -> Result<Hmac<Sha256>> {
+    let key = hkdf_sha256(&[], file_key, b"header")?;
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_ref())?;
+    mac.update(header);
+    Ok(mac)
+}
+
+fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, key.as_mut())
+        .map_err(|_| anyhow!("hkdf expand failed"))?;
+    Ok(key)
+}
+
+fn age_scrypt(passphrase: &str, salt: &[u8], work_factor: u8) -> Result<Zeroizing<[u8; 32]>> {
+    let params = scrypt::Params::new(work_factor, 8, 1, 32)
+        .map_err(|e| anyhow!("invalid scrypt parameters: {e}"))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    let salt = [AGE_SCRYPT_SALT, salt].concat();
+    scrypt::scrypt(passphrase.as_bytes(), &salt, &params, key.as_mut())
+        .map_err(|e| anyhow!("scrypt failed: {e}"))?;
+    Ok(key)
+}
+
+// every wrapping key is fresh, so the nonce is all zeros
+fn aead_wrap(key: &[u8; 32], file_key: &[u8; 16]) -> Result<Vec<u8>> {
+    ChaCha20Poly1305::new_from_slice(key)?
+        .encrypt(&Nonce::default(), file_key.as_slice())
+        .map_err(|_| anyhow!("file key wrapping failed"))
+}
+
+fn aead_unwrap(key: &[u8; 32], body: &[u8]) -> Option<Zeroizing<[u8; 16]>> {
+    let file_key = Zeroizing::new(
+        ChaCha20Poly1305::new_from_slice(key)
+            .ok()?
+            .decrypt(&Nonce::default(), body)
+            .ok()?,
+    );
+    Some(Zeroizing::new(file_key.as_slice().try_into().ok()?))
+}
+
+fn bech32_decode(s: &str, hrp: &str) -> Result<Zeroizing<[u8; 32]>> {
+    let (found, data, variant) = bech32::decode(s)?;
+    if found != hrp || variant != Variant::Bech32 {
+        bail!("{} is not an {} key", s, hrp.trim_end_matches('-'));
+    }
+    let data = Zeroizing::new(Vec::<u8>::from_base32(&data)?);
+    Ok(Zeroizing::new(data.as_slice().try_into().map_err(
+        |_| anyhow!("{} key is not 32 bytes", hrp.trim_end_matches('-')),
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::STREAM_TAG_SIZE;
+    use sha2::Digest;
+
+    // the identity of the age testkit (C2SP CCTV) vectors
+    const IDENTITY: &str =
+        "AGE-SECRET-KEY-1XMWWC06LY3EE5RYTXM9MFLAZ2U56JJJ36S0MYPDRWSVLUL66MV4QX3S7F6";
+
+    #[test]
+    fn test_age_x25519_round_trip() {
+        let identity =
+            X25519Identity::from_bytes(*bech32_decode(IDENTITY, AGE_IDENTITY_HRP).unwrap());
+        let recipient = age_recipient_encode(&identity.recipient()).unwrap();
+        assert!(recipient.starts_with("age1"));
+        let recipients = [AgeRecipient::parse(&recipient).unwrap()];
+        // an exact multiple of the chunk size ends with a full last chunk
+        let data = vec![7u8; STREAM_CHUNK_SIZE * 2];
+        for armor in [false, true] {
+            let mut encrypted = Vec::new();
+            age_encrypt(&mut data.as_slice(), &mut encrypted, &recipients, armor).unwrap();
+            assert!(is_age(&encrypted));
+            let mut decrypted = Vec::new();
+            let identities = [AgeIdentity::X25519(X25519Identity::from_bytes(
+                *bech32_decode(IDENTITY, AGE_IDENTITY_HRP).unwrap(),
+            ))];
+            age_decrypt(&mut encrypted.as_slice(), &mut decrypted, &identities).unwrap();
+            assert_eq!(decrypted, data);
+            if !armor {
+                // dropping the last chunk is noticed
+                let truncated = &encrypted[..encrypted.len() - STREAM_CHUNK_SIZE - STREAM_TAG_SIZE];
+                assert!(age_decrypt(&mut &truncated[..], &mut Vec::new(), &identities).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_age_testkit_vector() {
+        // the x25519 file of the age testkit, written by the reference implementation
+        let data = std::fs::read("fixtures/age_x25519.age").unwrap();
+        let identity = bech32_decode(IDENTITY, AGE_IDENTITY_HRP).unwrap();
+        let identities = [AgeIdentity::X25519(X25519Identity::from_bytes(*identity))];
+        let mut decrypted = Vec::new();
+        age_decrypt(&mut data.as_slice(), &mut decrypted, &identities).unwrap();
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&sha2::Sha256::digest(&decrypted)),
+            "013f54400c82da08037759ada907a8b864e97de81c088a182062c4b5622fd2ab"
+        );
+    }
+
+    #[test]
+    fn test_age_scrypt_round_trip() {
+        let passphrase = || Zeroizing::new("correct horse".to_string());
+        let mut encrypted = Vec::new();
+        let recipients = [AgeRecipient::Scrypt(passphrase(), 10)];
+        age_encrypt(&mut &b""[..], &mut encrypted, &recipients, false).unwrap();
+        let mut decrypted = Vec::new();
+        let identities = [AgeIdentity::Scrypt(passphrase())];
+        age_decrypt(&mut encrypted.as_slice(), &mut decrypted, &identities).unwrap();
+        assert!(decrypted.is_empty());
+        let wrong = [AgeIdentity::Scrypt(Zeroizing::new("wrong".to_string()))];
+        assert!(age_decrypt(&mut encrypted.as_slice(), &mut Vec::new(), &wrong).is_err());
+    }
+}
@@ -1,7 +1,28 @@
-use crate::B64Format;
-use anyhow::Result;
-use base64::prelude::*;
-use std::io::Read;
+use crate::{auto_decode, B64Format};
+use anyhow::{anyhow, bail, Result};
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    prelude::*,
+    read::DecoderReader,
+    write::EncoderWriter,
+    DecodeError,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+};
+
+const MIME_LINE_WIDTH: usize = 76;
+const PEM_LINE_WIDTH: usize = 64;
+
+// accepts missing, partial or extra padding and non-zero trailing bits
+const BASE64_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
 
 #[inline(always)]
 pub fn base64_encode(input: impl AsRef<[u8]>) -> String {
@@ -23,54 +44,320 @@ pub fn base64_url_decode(input: &str) -> Result<Vec<u8>> {
     Ok(BASE64_URL_SAFE_NO_PAD.decode(input)?)
 }
 
-pub fn b64_encode(input: impl AsRef<[u8]>, format: B64Format) -> Result<()> {
-    let encoded = match format {
-        B64Format::Std => base64_encode(input),
-        B64Format::Url => base64_url_encode(input),
+pub fn b64_encode(reader: &mut dyn Read, writer: &mut dyn Write, format: B64Format) -> Result<()> {
+    let Some(engine) = b64_engine(format) else {
+        bail!("auto format only works for decoding");
+    };
+    let mut writer: Box<dyn Write + '_> = match format {
+        B64Format::Mime => Box::new(LineWrapWriter::new(writer, MIME_LINE_WIDTH, b"\r\n")),
+        B64Format::Pem => Box::new(LineWrapWriter::new(writer, PEM_LINE_WIDTH, b"\n")),
+        _ => Box::new(writer),
     };
-    println!("{encoded}");
+    let mut encoder = EncoderWriter::new(&mut writer, engine);
+    io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
     Ok(())
 }
 
-pub fn b64_decode(input: &str, format: B64Format) -> Result<()> {
-    let input = read_input(input)?;
-    // avoid accidental newlines
-    let input = input.trim();
-    let decoded = match format {
-        B64Format::Std => base64_decode(input)?,
-        B64Format::Url => base64_url_decode(input)?,
+pub fn b64_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: B64Format,
+    lenient: bool,
+) -> Result<()> {
+    let Some(engine) = b64_engine(format) else {
+        // auto tries every base64 variant as it is, lenient decoding would make them all match
+        if lenient {
+            bail!("--lenient needs an explicit base64 format, not auto");
+        }
+        // detection needs to see all of the input
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        writer.write_all(&auto_decode(&input)?)?;
+        return Ok(());
     };
-    // TODO: maybe not string
-    let decoded = String::from_utf8(decoded)?;
-    println!("{decoded}");
-    Ok(())
+    let engine = if lenient { &BASE64_LENIENT } else { engine };
+    let mut decoder = DecoderReader::new(Base64Input::new(reader, lenient), engine);
+    match io::copy(&mut decoder, writer) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let Some(err) = e.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()) else {
+                return Err(e.into());
+            };
+            let input = decoder.into_inner();
+            Err(match *err {
+                DecodeError::InvalidByte(i, b) | DecodeError::InvalidLastSymbol(i, b) => {
+                    anyhow!(
+                        "invalid base64 character {:?} at offset {}",
+                        b as char,
+                        input.original_offset(i as u64)
+                    )
+                }
+                ref e => anyhow!("invalid base64 input: {e}"),
+            })
+        }
+    }
 }
 
-fn read_input(input: &str) -> Result<String> {
-    let input = if input == "-" {
-        let mut buffer = String::new();
-        std::io::stdin().read_to_string(&mut buffer)?;
-        buffer
-    } else {
-        // TODO: maybe not string
-        std::fs::read_to_string(input)?
-    };
-    Ok(input)
+// how many of the most recent offsets to remember, DecoderReader never looks further back
+const OFFSET_WINDOW: usize = 8 * 1024;
+
+// feeds the decoder with whitespace removed, and for lenient decoding also drops trailing
+// padding and maps url-safe chars to standard ones. Keeps the original offsets of the last bytes
+// it handed out, so decode errors can point at the right place in constant memory.
+struct Base64Input<'a> {
+    inner: &'a mut dyn Read,
+    lenient: bool,
+    consumed: u64,
+    produced: u64,
+    offsets: VecDeque<u64>,
+    // offset of the first of the '=' seen since the last data byte
+    padding: Option<u64>,
+}
+
+impl<'a> Base64Input<'a> {
+    fn new(inner: &'a mut dyn Read, lenient: bool) -> Self {
+        Self {
+            inner,
+            lenient,
+            consumed: 0,
+            produced: 0,
+            offsets: VecDeque::with_capacity(OFFSET_WINDOW),
+            padding: None,
+        }
+    }
+
+    fn original_offset(&self, produced: u64) -> u64 {
+        let window_start = self.produced - self.offsets.len() as u64;
+        match produced.checked_sub(window_start) {
+            Some(i) if (i as usize) < self.offsets.len() => self.offsets[i as usize],
+            _ => produced,
+        }
+    }
+}
+
+impl Read for Base64Input<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                let offset = self.consumed + i as u64;
+                let b = match buf[i] {
+                    b if b.is_ascii_whitespace() => continue,
+                    b'=' if self.lenient => {
+                        self.padding.get_or_insert(offset);
+                        continue;
+                    }
+                    // padding followed by more data, concatenated blobs most likely
+                    _ if self.padding.is_some() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "base64 padding in the middle of the input at offset {}",
+                                self.padding.unwrap_or_default()
+                            ),
+                        ));
+                    }
+                    b'-' if self.lenient => b'+',
+                    b'_' if self.lenient => b'/',
+                    b => b,
+                };
+                buf[kept] = b;
+                kept += 1;
+                if self.offsets.len() == OFFSET_WINDOW {
+                    self.offsets.pop_front();
+                }
+                self.offsets.push_back(offset);
+            }
+            self.consumed += n as u64;
+            self.produced += kept as u64;
+            // a chunk of only whitespace is not EOF, keep reading
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+// none for auto, which isn't one engine but a guess between them
+fn b64_engine(format: B64Format) -> Option<&'static GeneralPurpose> {
+    match format {
+        B64Format::Std | B64Format::Mime | B64Format::Pem => Some(&BASE64_STANDARD),
+        B64Format::StdNoPad => Some(&BASE64_STANDARD_NO_PAD),
+        B64Format::Url => Some(&BASE64_URL_SAFE_NO_PAD),
+        B64Format::UrlPad => Some(&BASE64_URL_SAFE),
+        B64Format::Auto => None,
+    }
+}
+
+// inserts `eol` after every `width` bytes, for MIME and PEM style output
+struct LineWrapWriter<W: Write> {
+    inner: W,
+    width: usize,
+    eol: &'static [u8],
+    column: usize,
+}
+
+impl<W: Write> LineWrapWriter<W> {
+    fn new(inner: W, width: usize, eol: &'static [u8]) -> Self {
+        Self {
+            inner,
+            width,
+            eol,
+            column: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for LineWrapWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            if self.column == self.width {
+                self.inner.write_all(self.eol)?;
+                self.column = 0;
+            }
+            let n = (self.width - self.column).min(buf.len() - written);
+            self.inner.write_all(&buf[written..written + n])?;
+            self.column += n;
+            written += n;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn decode(reader: &mut dyn Read, format: B64Format, lenient: bool) -> Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        b64_decode(reader, &mut decoded, format, lenient)?;
+        Ok(decoded)
+    }
+
+    fn encode_fixture(path: &str, format: B64Format) {
+        let expected = std::fs::read_to_string(path).unwrap();
+        let expected = expected.trim();
+        let data = match format {
+            B64Format::Url => base64_url_decode(expected).unwrap(),
+            _ => base64_decode(expected).unwrap(),
+        };
+        let mut encoded = Vec::new();
+        b64_encode(&mut data.as_slice(), &mut encoded, format).unwrap();
+        assert_eq!(String::from_utf8(encoded).unwrap(), expected);
+    }
+
     #[test]
     fn test_b64_encode() {
-        assert!(b64_encode("Cargo.toml", B64Format::Std).is_ok());
-        assert!(b64_encode("Cargo.toml", B64Format::Url).is_ok());
+        encode_fixture("fixtures/std.txt", B64Format::Std);
+        encode_fixture("fixtures/url.txt", B64Format::Url);
+
+        let mut reader = std::fs::File::open("Cargo.toml").unwrap();
+        let mut encoded = Vec::new();
+        b64_encode(&mut reader, &mut encoded, B64Format::Std).unwrap();
+        let decoded = base64_decode(std::str::from_utf8(&encoded).unwrap()).unwrap();
+        assert_eq!(decoded, std::fs::read("Cargo.toml").unwrap());
     }
 
     #[test]
     fn test_b64_decode() {
-        assert!(b64_decode("fixtures/std.txt", B64Format::Std).is_ok());
-        assert!(b64_decode("fixtures/url.txt", B64Format::Url).is_ok());
+        for (path, format) in [
+            ("fixtures/std.txt", B64Format::Std),
+            ("fixtures/url.txt", B64Format::Url),
+        ] {
+            let mut reader = std::fs::File::open(path).unwrap();
+            let decoded = decode(&mut reader, format, false).unwrap();
+            assert!(decoded.starts_with(b"[package]"));
+            let mut encoded = Vec::new();
+            b64_encode(&mut decoded.as_slice(), &mut encoded, format).unwrap();
+            assert_eq!(encoded, std::fs::read(path).unwrap());
+        }
+
+        let binary = [0u8, 159, 146, 150, 255];
+        let encoded = base64_encode(binary);
+        let decoded = decode(&mut encoded.as_bytes(), B64Format::Std, false).unwrap();
+        assert_eq!(decoded, binary);
+
+        let err = decode(&mut "aGVs\nbG8*".as_bytes(), B64Format::Std, false).unwrap_err();
+        assert_eq!(err.to_string(), "invalid base64 character '*' at offset 8");
+    }
+
+    #[test]
+    fn test_b64_variants() {
+        let data = b"\xfb\xff hello";
+        let encode = |format| {
+            let mut encoded = Vec::new();
+            b64_encode(&mut &data[..], &mut encoded, format).unwrap();
+            String::from_utf8(encoded).unwrap()
+        };
+        assert_eq!(encode(B64Format::Std), "+/8gaGVsbG8=");
+        assert_eq!(encode(B64Format::StdNoPad), "+/8gaGVsbG8");
+        assert_eq!(encode(B64Format::Url), "-_8gaGVsbG8");
+        assert_eq!(encode(B64Format::UrlPad), "-_8gaGVsbG8=");
+
+        let data = vec![0u8; 100];
+        let mut encoded = Vec::new();
+        b64_encode(&mut data.as_slice(), &mut encoded, B64Format::Mime).unwrap();
+        let encoded = String::from_utf8(encoded).unwrap();
+        let lines: Vec<_> = encoded.split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), MIME_LINE_WIDTH);
+        let decoded = decode(&mut encoded.as_bytes(), B64Format::Mime, false).unwrap();
+        assert_eq!(decoded, data);
+
+        let mut encoded = Vec::new();
+        b64_encode(&mut data.as_slice(), &mut encoded, B64Format::Pem).unwrap();
+        let encoded = String::from_utf8(encoded).unwrap();
+        assert!(encoded.lines().all(|l| l.len() <= PEM_LINE_WIDTH));
+        assert_eq!(encoded.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_b64_decode_lenient() {
+        let expected = b"\xfb\xff hello";
+        for input in [
+            "+/8gaGVsbG8=",
+            "-_8gaGVsbG8",
+            "+/8g\r\naGVs\nbG8==",
+            "-_8gaGVsbG9",
+        ] {
+            let decoded = decode(&mut input.as_bytes(), B64Format::Std, true).unwrap();
+            assert_eq!(decoded, expected, "{input}");
+        }
+        assert!(decode(&mut "-_8gaGVsbG8".as_bytes(), B64Format::Std, false).is_err());
+        assert!(decode(&mut "aGk".as_bytes(), B64Format::Auto, true).is_err());
+        // padding is only dropped at the end, not between concatenated blobs
+        let err = decode(&mut "aGk=\naGk=".as_bytes(), B64Format::Std, true).unwrap_err();
+        assert!(err.to_string().contains("offset 3"), "{err}");
+    }
+
+    #[test]
+    fn test_b64_decode_stream() {
+        // several DecoderReader buffers worth of wrapped input
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Vec::new();
+        b64_encode(&mut data.as_slice(), &mut encoded, B64Format::Mime).unwrap();
+        assert_eq!(
+            decode(&mut encoded.as_slice(), B64Format::Std, false).unwrap(),
+            data
+        );
+
+        // the error offset still points into the original input far into the stream
+        let bad = encoded.len() - 10;
+        encoded[bad] = b'*';
+        let err = decode(&mut encoded.as_slice(), B64Format::Std, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("invalid base64 character '*' at offset {bad}")
+        );
     }
 }
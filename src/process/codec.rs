@@ -0,0 +1,550 @@
+use crate::{b64_decode, b64_encode, B64Format, CodecName, EscapeCodec};
+use anyhow::{anyhow, bail, Result};
+use data_encoding::{Encoding, Specification, BASE32, HEXLOWER, HEXLOWER_PERMISSIVE, HEXUPPER};
+use std::io::{Read, Write};
+
+// read this many bytes at a time, rounded down to a multiple of the codec block size
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const BASE85_RFC1924: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+const BASE85_Z85: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+pub trait Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+}
+
+pub fn get_codec(name: CodecName) -> Result<Box<dyn Codec>> {
+    let codec: Box<dyn Codec> = match name {
+        CodecName::Hex => Box::new(BlockCodec::new(name, HEXLOWER, HEXLOWER_PERMISSIVE)),
+        CodecName::HexUpper => Box::new(BlockCodec::new(name, HEXUPPER, HEXLOWER_PERMISSIVE)),
+        CodecName::Base32 => Box::new(BlockCodec::new(name, BASE32, BASE32)),
+        CodecName::Base32Crockford => {
+            let crockford = crockford_encoding()?;
+            Box::new(BlockCodec::new(name, crockford.clone(), crockford))
+        }
+        CodecName::Base58 => Box::new(Base58Codec { check: false }),
+        CodecName::Base58Check => Box::new(Base58Codec { check: true }),
+        CodecName::Base85 => Box::new(Base85Codec::new(Base85Variant::Rfc1924)),
+        CodecName::Z85 => Box::new(Base85Codec::new(Base85Variant::Z85)),
+        CodecName::Ascii85 => Box::new(Base85Codec::new(Base85Variant::Ascii85)),
+        CodecName::Base64 => Box::new(Base64Codec(B64Format::Std)),
+        CodecName::Base64NoPad => Box::new(Base64Codec(B64Format::StdNoPad)),
+        CodecName::Base64Url => Box::new(Base64Codec(B64Format::Url)),
+        CodecName::Base64UrlPad => Box::new(Base64Codec(B64Format::UrlPad)),
+        CodecName::Url
+        | CodecName::UrlComponent
+        | CodecName::Form
+        | CodecName::Html
+        | CodecName::JsonString
+        | CodecName::UnicodeEscape => Box::new(EscapeCodec(name)),
+        CodecName::Auto => Box::new(AutoCodec),
+    };
+    Ok(codec)
+}
+
+// hex and base32 work on fixed size blocks, so they can be streamed chunk by chunk
+struct BlockCodec {
+    name: CodecName,
+    encoder: Encoding,
+    decoder: Encoding,
+}
+
+impl BlockCodec {
+    fn new(name: CodecName, encoder: Encoding, decoder: Encoding) -> Self {
+        Self {
+            name,
+            encoder,
+            decoder,
+        }
+    }
+}
+
+impl Codec for BlockCodec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let block = self.encoder.encode_align();
+        let mut buf = vec![0u8; CHUNK_SIZE / block * block];
+        loop {
+            let n = read_full(reader, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(self.encoder.encode(&buf[..n]).as_bytes())?;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let block = self.decoder.encode_len(self.decoder.encode_align());
+        let mut decoded = 0;
+        let mut decode = |input: &[u8], writer: &mut dyn Write| -> Result<()> {
+            let output = self.decoder.decode(input).map_err(|e| {
+                anyhow!(
+                    "invalid {} input at offset {} (whitespace ignored): {}",
+                    self.name,
+                    decoded + e.position,
+                    e.kind
+                )
+            })?;
+            decoded += input.len();
+            writer.write_all(&output)?;
+            Ok(())
+        };
+        let mut pending = Vec::new();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = read_full(reader, &mut buf)?;
+            pending.extend(buf[..n].iter().filter(|b| !b.is_ascii_whitespace()));
+            if n < buf.len() {
+                break;
+            }
+            let ready = pending.len() / block * block;
+            decode(&pending[..ready], writer)?;
+            pending.drain(..ready);
+        }
+        decode(&pending, writer)
+    }
+}
+
+// base58 is a big number conversion, every output digit depends on the whole input,
+// so unlike the other codecs it has to buffer everything
+struct Base58Codec {
+    check: bool,
+}
+
+impl Codec for Base58Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let encoded = if self.check {
+            bs58::encode(data).with_check().into_string()
+        } else {
+            bs58::encode(data).into_string()
+        };
+        writer.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut data = String::new();
+        reader.read_to_string(&mut data)?;
+        let data = data.trim();
+        let decoded = if self.check {
+            bs58::decode(data).with_check(None).into_vec()?
+        } else {
+            bs58::decode(data).into_vec()?
+        };
+        writer.write_all(&decoded)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Base85Variant {
+    // the alphabet used by git and python's b85encode
+    Rfc1924,
+    // ZeroMQ, input must be a multiple of 4 bytes
+    Z85,
+    // Adobe/btoa, 'z' stands for 4 zero bytes
+    Ascii85,
+}
+
+struct Base85Codec {
+    variant: Base85Variant,
+    alphabet: [u8; 85],
+    lookup: [u8; 256],
+}
+
+impl Base85Codec {
+    fn new(variant: Base85Variant) -> Self {
+        let alphabet = match variant {
+            Base85Variant::Rfc1924 => *BASE85_RFC1924,
+            Base85Variant::Z85 => *BASE85_Z85,
+            Base85Variant::Ascii85 => std::array::from_fn(|i| b'!' + i as u8),
+        };
+        let mut lookup = [0xff; 256];
+        for (i, c) in alphabet.iter().enumerate() {
+            lookup[*c as usize] = i as u8;
+        }
+        Self {
+            variant,
+            alphabet,
+            lookup,
+        }
+    }
+
+    fn encode_block(&self, block: &[u8], output: &mut Vec<u8>) {
+        let mut word = [0u8; 4];
+        word[..block.len()].copy_from_slice(block);
+        let mut value = u32::from_be_bytes(word);
+        if self.variant == Base85Variant::Ascii85 && block.len() == 4 && value == 0 {
+            output.push(b'z');
+            return;
+        }
+        let mut digits = [0u8; 5];
+        for d in digits.iter_mut().rev() {
+            *d = self.alphabet[(value % 85) as usize];
+            value /= 85;
+        }
+        // a partial block of n bytes only needs n + 1 digits
+        output.extend_from_slice(&digits[..block.len() + 1]);
+    }
+
+    fn decode_block(&self, digits: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let mut value: u64 = 0;
+        for i in 0..5 {
+            // pad partial blocks with the highest digit, so truncation rounds correctly
+            let d = digits.get(i).copied().unwrap_or(84);
+            value = value * 85 + d as u64;
+        }
+        let value = u32::try_from(value).map_err(|_| anyhow!("invalid base85 block"))?;
+        output.extend_from_slice(&value.to_be_bytes()[..digits.len() - 1]);
+        Ok(())
+    }
+}
+
+impl Codec for Base85Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut buf = vec![0u8; CHUNK_SIZE / 4 * 4];
+        loop {
+            let n = read_full(reader, &mut buf)?;
+            if n % 4 != 0 && self.variant == Base85Variant::Z85 {
+                bail!("z85 input length must be a multiple of 4");
+            }
+            let mut output = Vec::with_capacity(n / 4 * 5 + 5);
+            for block in buf[..n].chunks(4) {
+                self.encode_block(block, &mut output);
+            }
+            writer.write_all(&output)?;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut digits = Vec::with_capacity(5);
+        let mut offset = 0;
+        let mut first = true;
+        'outer: loop {
+            let n = read_full(reader, &mut buf)?;
+            let mut input = &buf[..n];
+            if first && self.variant == Base85Variant::Ascii85 {
+                let trimmed = input.trim_ascii_start();
+                if trimmed.starts_with(b"<~") {
+                    offset += n - trimmed.len() + 2;
+                    input = &trimmed[2..];
+                }
+                first = false;
+            }
+            let mut output = Vec::with_capacity(n / 5 * 4 + 4);
+            for &c in input {
+                offset += 1;
+                if c.is_ascii_whitespace() {
+                    continue;
+                }
+                if self.variant == Base85Variant::Ascii85 {
+                    if c == b'~' {
+                        writer.write_all(&output)?;
+                        break 'outer;
+                    }
+                    if c == b'z' && digits.is_empty() {
+                        output.extend_from_slice(&[0; 4]);
+                        continue;
+                    }
+                }
+                let d = self.lookup[c as usize];
+                if d == 0xff {
+                    bail!(
+                        "invalid base85 character {:?} at offset {}",
+                        c as char,
+                        offset - 1
+                    );
+                }
+                digits.push(d);
+                if digits.len() == 5 {
+                    self.decode_block(&digits, &mut output)?;
+                    digits.clear();
+                }
+            }
+            writer.write_all(&output)?;
+            if n < buf.len() {
+                break;
+            }
+        }
+        match digits.len() {
+            0 => Ok(()),
+            _ if self.variant == Base85Variant::Z85 => {
+                bail!("z85 input length must be a multiple of 5")
+            }
+            1 => bail!("truncated base85 input"),
+            _ => {
+                let mut output = Vec::with_capacity(4);
+                self.decode_block(&digits, &mut output)?;
+                writer.write_all(&output)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Base64Codec(B64Format);
+
+impl Codec for Base64Codec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        b64_encode(reader, writer, self.0)
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        b64_decode(reader, writer, self.0, false)
+    }
+}
+
+struct AutoCodec;
+
+impl Codec for AutoCodec {
+    fn encode(&self, _reader: &mut dyn Read, _writer: &mut dyn Write) -> Result<()> {
+        bail!("auto codec only works for decoding")
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        writer.write_all(&auto_decode(&input)?)?;
+        Ok(())
+    }
+}
+
+// candidates in order of preference, the smaller the alphabet the less likely a match is by accident
+pub fn detect_codec(input: &[u8]) -> Vec<CodecName> {
+    let input: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    if input.is_empty() {
+        return Vec::new();
+    }
+    let body = input.trim_ascii_end();
+    let padded = body.ends_with(b"=");
+    let data = &body[..body.iter().rposition(|b| *b != b'=').map_or(0, |i| i + 1)];
+    let len = body.len();
+    let only = |f: fn(&u8) -> bool| data.iter().all(f);
+    let is_std = |b: &u8| b.is_ascii_alphanumeric() || *b == b'+' || *b == b'/';
+    let is_url = |b: &u8| b.is_ascii_alphanumeric() || *b == b'-' || *b == b'_';
+
+    let mut candidates = Vec::new();
+    if !padded && len.is_multiple_of(2) && only(u8::is_ascii_hexdigit) {
+        candidates.push(CodecName::Hex);
+    }
+    if len.is_multiple_of(8) && only(|b| b.is_ascii_uppercase() || (b'2'..=b'7').contains(b)) {
+        candidates.push(CodecName::Base32);
+    }
+    // a base64 string never leaves a single char in its last group
+    if padded && len.is_multiple_of(4) {
+        if only(is_std) {
+            candidates.push(CodecName::Base64);
+        }
+        if only(is_url) {
+            candidates.push(CodecName::Base64UrlPad);
+        }
+    } else if !padded && len % 4 != 1 {
+        if only(is_std) {
+            candidates.push(CodecName::Base64NoPad);
+        }
+        if only(is_url) {
+            candidates.push(CodecName::Base64Url);
+        }
+    }
+    candidates
+}
+
+pub fn auto_decode(input: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded: Vec<(CodecName, Vec<u8>)> = Vec::new();
+    for name in detect_codec(input) {
+        let mut output = Vec::new();
+        if get_codec(name)?
+            .decode(&mut &input[..], &mut output)
+            .is_ok()
+        {
+            decoded.push((name, output));
+        }
+    }
+    let mut decoded = decoded.into_iter();
+    let Some((chosen, output)) = decoded.next() else {
+        bail!("cannot detect the encoding, input is not valid hex, base32 or base64");
+    };
+    // only mention alternatives that would actually give different bytes
+    let others: Vec<String> = decoded
+        .filter(|(_, o)| *o != output)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    if others.is_empty() {
+        eprintln!("detected encoding: {chosen}");
+    } else {
+        eprintln!(
+            "ambiguous input, decoding as {chosen}, could also be: {}. Pass an explicit format to choose",
+            others.join(", ")
+        );
+    }
+    Ok(output)
+}
+
+fn crockford_encoding() -> Result<Encoding> {
+    let mut spec = Specification::new();
+    spec.symbols.push_str("0123456789ABCDEFGHJKMNPQRSTVWXYZ");
+    // decoding is case insensitive and forgiving about look-alike characters
+    spec.translate.from.push_str("abcdefghjkmnpqrstvwxyzIiLlOo");
+    spec.translate.to.push_str("ABCDEFGHJKMNPQRSTVWXYZ111100");
+    Ok(spec.encoding()?)
+}
+
+// fill buf as much as possible, a short count means EOF
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(name: CodecName, data: &[u8]) -> String {
+        let mut output = Vec::new();
+        get_codec(name)
+            .unwrap()
+            .encode(&mut &data[..], &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn decode(name: CodecName, data: &str) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        get_codec(name)?.decode(&mut data.as_bytes(), &mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_codec_vectors() {
+        let vectors: &[(CodecName, &[u8], &str)] = &[
+            (CodecName::Hex, b"hello", "68656c6c6f"),
+            (CodecName::HexUpper, b"hello", "68656C6C6F"),
+            (CodecName::Base32, b"foobar", "MZXW6YTBOI======"),
+            (CodecName::Base32Crockford, b"foobar", "CSQPYRK1E8"),
+            (CodecName::Base58, b"hello world", "StV1DL6CwTryKyV"),
+            (
+                CodecName::Base58Check,
+                b"hello world",
+                "3vQB7B6MrGQZaxCuFg4oh",
+            ),
+            (CodecName::Base85, b"hello", "Xk~0{Zv"),
+            (
+                CodecName::Z85,
+                &[0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b],
+                "HelloWorld",
+            ),
+            (CodecName::Ascii85, b"Man sure.", "9jqo^F*2M7/c"),
+            (CodecName::Ascii85, &[0, 0, 0, 0, 1], "z!<"),
+            (CodecName::Base64, b"hello", "aGVsbG8="),
+        ];
+        for (name, data, encoded) in vectors {
+            assert_eq!(&encode(*name, data), encoded, "{name}");
+            assert_eq!(&decode(*name, encoded).unwrap(), data, "{name}");
+        }
+        assert_eq!(decode(CodecName::Hex, "68656C6c6f\n").unwrap(), b"hello");
+        assert_eq!(
+            decode(CodecName::Base32Crockford, "csqpyrkle8").unwrap(),
+            b"foobar"
+        );
+        assert_eq!(
+            decode(CodecName::Ascii85, "<~9jqo^F*2M7/c~>").unwrap(),
+            b"Man sure."
+        );
+    }
+
+    #[test]
+    fn test_codec_round_trip() {
+        // cross a chunk boundary with a size that is not block aligned
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 7)
+            .map(|i| (i * 31 % 251) as u8)
+            .collect();
+        for name in [
+            CodecName::Hex,
+            CodecName::HexUpper,
+            CodecName::Base32,
+            CodecName::Base32Crockford,
+            CodecName::Base58Check,
+            CodecName::Base85,
+            CodecName::Ascii85,
+            CodecName::Base64Url,
+        ] {
+            // base58 is quadratic, keep its input small
+            let data = if let CodecName::Base58Check = name {
+                &data[..1000]
+            } else {
+                &data[..]
+            };
+            let encoded = encode(name, data);
+            assert_eq!(decode(name, &encoded).unwrap(), data, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_detect_codec() {
+        use CodecName::*;
+        let cases: &[(&str, &[CodecName])] = &[
+            ("68656c6c6f", &[Hex, Base64NoPad, Base64Url]),
+            ("MZXW6YTBOI======", &[Base32, Base64, Base64UrlPad]),
+            ("aGVsbG8=", &[Base64, Base64UrlPad]),
+            ("aGVsbG8", &[Base64NoPad, Base64Url]),
+            ("+/8gaGVsbG8", &[Base64NoPad]),
+            ("-_8gaGVsbG8=", &[Base64UrlPad]),
+            // a JWT header segment
+            ("eyJhbGciOiJIUzI1NiJ9", &[Base64NoPad, Base64Url]),
+            ("not base64!", &[]),
+        ];
+        for (input, expected) in cases {
+            let detected: Vec<_> = detect_codec(input.as_bytes())
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            let expected: Vec<_> = expected.iter().map(|n| n.to_string()).collect();
+            assert_eq!(detected, expected, "{input}");
+        }
+
+        assert_eq!(auto_decode(b"68656c6c6f\n").unwrap(), b"hello");
+        assert_eq!(auto_decode(b"MZXW6YTBOI======").unwrap(), b"foobar");
+        assert_eq!(auto_decode(b"-_8gaGVsbG8").unwrap(), b"\xfb\xff hello");
+        assert_eq!(
+            auto_decode(b"eyJhbGciOiJIUzI1NiJ9").unwrap(),
+            br#"{"alg":"HS256"}"#
+        );
+        assert!(auto_decode(b"not base64!").is_err());
+    }
+
+    #[test]
+    fn test_codec_errors() {
+        let err = decode(CodecName::Hex, "6865zz").unwrap_err();
+        assert!(err.to_string().contains("offset 4"), "{err}");
+        let err = decode(CodecName::Base85, "Xk~0\"Zv").unwrap_err();
+        assert_eq!(err.to_string(), "invalid base85 character '\"' at offset 4");
+        assert!(decode(CodecName::Base58Check, "StV1DL6CwTryKyV").is_err());
+        assert!(decode(CodecName::Z85, "Hello").is_ok());
+        assert!(decode(CodecName::Z85, "Hell").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{base64_decode, base64_encode};
+use anyhow::{anyhow, Result};
+use percent_encoding::percent_decode_str;
+use std::path::Path;
+
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+#[derive(Debug)]
+pub struct DataUri {
+    pub mime: String,
+    pub data: Vec<u8>,
+}
+
+pub fn datauri_encode(data: &[u8], path: Option<&Path>, mime: Option<&str>) -> String {
+    let mime = match mime {
+        Some(mime) => mime.to_string(),
+        None => sniff_mime(data, path),
+    };
+    format!("data:{mime};base64,{}", base64_encode(data))
+}
+
+pub fn datauri_decode(uri: &str) -> Result<DataUri> {
+    let uri = uri.trim();
+    let rest = uri
+        .strip_prefix("data:")
+        .ok_or_else(|| anyhow!("not a data URI, it must start with \"data:\""))?;
+    let (meta, payload) = rest
+        .split_once(',')
+        .ok_or_else(|| anyhow!("invalid data URI, missing ','"))?;
+    let mut params = meta.split(';');
+    // RFC 2397: an empty media type means text/plain
+    let mime = match params.next() {
+        Some(mime) if !mime.is_empty() => mime.to_string(),
+        _ => "text/plain".to_string(),
+    };
+    let data = if params.any(|p| p.eq_ignore_ascii_case("base64")) {
+        let payload = percent_decode_str(payload).decode_utf8()?;
+        let payload: String = payload.split_ascii_whitespace().collect();
+        base64_decode(&payload)?
+    } else {
+        percent_decode_str(payload).collect()
+    };
+    Ok(DataUri { mime, data })
+}
+
+// magic bytes win over the extension, a renamed file still has the right content
+pub fn sniff_mime(data: &[u8], path: Option<&Path>) -> String {
+    if let Some(kind) = infer::get(data) {
+        return kind.mime_type().to_string();
+    }
+    path.and_then(|p| mime_guess::from_path(p).first_raw())
+        .unwrap_or(DEFAULT_MIME)
+        .to_string()
+}
+
+pub fn mime_extension(mime: &str) -> &'static str {
+    // mime_guess lists extensions alphabetically, prefer the usual one for common types
+    match mime {
+        "text/plain" => "txt",
+        "text/html" => "html",
+        "text/css" => "css",
+        "text/javascript" | "application/javascript" => "js",
+        "application/json" => "json",
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        _ => mime_guess::get_mime_extensions_str(mime)
+            .and_then(|exts| exts.first().copied())
+            .unwrap_or("bin"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datauri() {
+        let path = Path::new("assets/index.html");
+        let data = std::fs::read(path).unwrap();
+        let uri = datauri_encode(&data, Some(path), None);
+        assert!(uri.starts_with("data:text/html;base64,"));
+        let decoded = datauri_decode(&uri).unwrap();
+        assert_eq!(decoded.mime, "text/html");
+        assert_eq!(decoded.data, data);
+        assert_eq!(mime_extension(&decoded.mime), "html");
+
+        // PNG magic bytes beat a misleading extension
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let uri = datauri_encode(png, Some(Path::new("logo.txt")), None);
+        assert!(uri.starts_with("data:image/png;base64,"));
+        assert_eq!(mime_extension("image/png"), "png");
+
+        let decoded = datauri_decode("data:,Hello%2C%20World%21").unwrap();
+        assert_eq!(decoded.mime, "text/plain");
+        assert_eq!(decoded.data, b"Hello, World!");
+        let decoded = datauri_decode("data:text/plain;charset=utf-8;base64,aGk=").unwrap();
+        assert_eq!(decoded.data, b"hi");
+        assert!(datauri_decode("http://example.com").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{Codec, CodecName};
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::io::{Read, Write};
+
+// what encodeURIComponent leaves alone
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+// encodeURI also keeps the characters that give a URL its structure
+const URL: &AsciiSet = &URL_COMPONENT
+    .remove(b';')
+    .remove(b',')
+    .remove(b'/')
+    .remove(b'?')
+    .remove(b':')
+    .remove(b'@')
+    .remove(b'&')
+    .remove(b'=')
+    .remove(b'+')
+    .remove(b'$')
+    .remove(b'#');
+
+// application/x-www-form-urlencoded, spaces become '+'
+const FORM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'*')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_');
+
+// text escaping for URLs, HTML, JSON string literals and \u escapes, they work on the whole input
+pub struct EscapeCodec(pub CodecName);
+
+impl Codec for EscapeCodec {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        let encoded = match self.0 {
+            CodecName::Url => percent_encode(&input, URL).to_string(),
+            CodecName::UrlComponent => percent_encode(&input, URL_COMPONENT).to_string(),
+            CodecName::Form => percent_encode(&input, FORM).to_string().replace("%20", "+"),
+            CodecName::Html => {
+                html_escape::encode_double_quoted_attribute(as_text(&input, self.0)?).into_owned()
+            }
+            CodecName::JsonString => serde_json::to_string(as_text(&input, self.0)?)?,
+            CodecName::UnicodeEscape => unicode_escape(as_text(&input, self.0)?),
+            name => bail!("{} is not an escaping codec", name),
+        };
+        writer.write_all(encoded.as_bytes())?;
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        // the line ending echo or an editor adds is not part of the escaped text
+        let input = input.strip_suffix(b"\n").unwrap_or(&input);
+        let input = input.strip_suffix(b"\r").unwrap_or(input);
+        let decoded: Vec<u8> = match self.0 {
+            CodecName::Url | CodecName::UrlComponent => percent_decode(input).collect(),
+            CodecName::Form => {
+                let input: Vec<u8> = input
+                    .iter()
+                    .map(|&b| if b == b'+' { b' ' } else { b })
+                    .collect();
+                percent_decode(&input).collect()
+            }
+            CodecName::Html => html_escape::decode_html_entities(as_text(input, self.0)?)
+                .into_owned()
+                .into_bytes(),
+            CodecName::JsonString => {
+                let input = as_text(input, self.0)?.trim();
+                // accept the literal with or without its quotes
+                let decoded: String = if input.starts_with('"') {
+                    serde_json::from_str(input)?
+                } else {
+                    serde_json::from_str(&format!("\"{input}\""))?
+                };
+                decoded.into_bytes()
+            }
+            CodecName::UnicodeEscape => unicode_unescape(as_text(input, self.0)?)?.into_bytes(),
+            name => bail!("{} is not an escaping codec", name),
+        };
+        writer.write_all(&decoded)?;
+        Ok(())
+    }
+}
+
+fn as_text(input: &[u8], name: CodecName) -> Result<&str> {
+    std::str::from_utf8(input).map_err(|e| anyhow!("{} needs UTF-8 input: {}", name, e))
+}
+
+// JavaScript/Java style, everything outside printable ASCII becomes \uXXXX,
+// with surrogate pairs above the BMP
+fn unicode_escape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            ' '..='~' => output.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    output.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    output
+}
+
+// understands \uXXXX (and surrogate pairs), \u{X...}, \xHH and the usual one char escapes
+fn unicode_unescape(input: &str) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        let Some((_, escape)) = chars.next() else {
+            bail!("dangling backslash at offset {}", i);
+        };
+        let invalid = || anyhow!("invalid escape at offset {}", i);
+        let c = match escape {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            '\\' | '"' | '\'' | '/' => escape,
+            'x' => char::from_u32(take_hex(&mut chars, 2).ok_or_else(invalid)?)
+                .expect("two hex digits are a valid char"),
+            'u' if chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                chars.next();
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => digits.push(c),
+                        None => bail!("unterminated \\u{{ escape at offset {}", i),
+                    }
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(invalid)?
+            }
+            'u' => {
+                let unit = take_hex(&mut chars, 4).ok_or_else(invalid)?;
+                if (0xd800..0xdc00).contains(&unit) {
+                    // a high surrogate has to be followed by an escaped low one
+                    let low = match (chars.next(), chars.next()) {
+                        (Some((_, '\\')), Some((_, 'u'))) => {
+                            take_hex(&mut chars, 4).ok_or_else(invalid)?
+                        }
+                        _ => bail!("unpaired surrogate at offset {}", i),
+                    };
+                    char::decode_utf16([unit as u16, low as u16])
+                        .next()
+                        .and_then(|c| c.ok())
+                        .ok_or_else(|| anyhow!("unpaired surrogate at offset {}", i))?
+                } else {
+                    char::from_u32(unit)
+                        .ok_or_else(|| anyhow!("unpaired surrogate at offset {}", i))?
+                }
+            }
+            c => bail!("unknown escape \\{} at offset {}", c, i),
+        };
+        output.push(c);
+    }
+    Ok(output)
+}
+
+fn take_hex(chars: &mut impl Iterator<Item = (usize, char)>, n: usize) -> Option<u32> {
+    let digits: String = chars.take(n).map(|(_, c)| c).collect();
+    match digits.len() == n {
+        true => u32::from_str_radix(&digits, 16).ok(),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(name: CodecName, data: &str) -> String {
+        let mut output = Vec::new();
+        EscapeCodec(name)
+            .encode(&mut data.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn decode(name: CodecName, data: &str) -> Result<String> {
+        let mut output = Vec::new();
+        EscapeCodec(name).decode(&mut data.as_bytes(), &mut output)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn test_escape_codecs() {
+        let vectors: &[(CodecName, &str, &str)] = &[
+            (
+                CodecName::Url,
+                "https://x.io/a b?q=1&r=ü",
+                "https://x.io/a%20b?q=1&r=%C3%BC",
+            ),
+            (
+                CodecName::UrlComponent,
+                "a b&c=d/é",
+                "a%20b%26c%3Dd%2F%C3%A9",
+            ),
+            (CodecName::Form, "a b&c=d+e*", "a+b%26c%3Dd%2Be*"),
+            (
+                CodecName::Html,
+                "<a href=\"x\">Tom & 'Jerry'</a>",
+                "&lt;a href=&quot;x&quot;&gt;Tom &amp; 'Jerry'&lt;/a&gt;",
+            ),
+            (
+                CodecName::JsonString,
+                "line\n\"quoted\"\ttab\\",
+                r#""line\n\"quoted\"\ttab\\""#,
+            ),
+            (
+                CodecName::UnicodeEscape,
+                "héllo 😀\\",
+                r"h\u00e9llo \ud83d\ude00\\",
+            ),
+        ];
+        for (name, data, encoded) in vectors {
+            assert_eq!(&encode(*name, data), encoded, "{name}");
+            assert_eq!(&decode(*name, encoded).unwrap(), data, "{name}");
+        }
+
+        assert_eq!(
+            decode(CodecName::Html, "&copy; &eacute; &#x41;&#66;\n").unwrap(),
+            "© é AB"
+        );
+        assert_eq!(decode(CodecName::JsonString, r"caf\u00e9").unwrap(), "café");
+        assert_eq!(
+            decode(CodecName::UnicodeEscape, r"\u{1F600}\x41\n").unwrap(),
+            "😀A\n"
+        );
+        assert!(decode(CodecName::UnicodeEscape, r"\ud83d").is_err());
+        assert!(decode(CodecName::UnicodeEscape, r"\q").is_err());
+        assert!(decode(CodecName::UnicodeEscape, r"\u{41").is_err());
+    }
+}
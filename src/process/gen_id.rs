@@ -0,0 +1,125 @@
+use crate::{base64_url_encode, TokenEncoding};
+use anyhow::{bail, Result};
+use rand::{seq::SliceRandom, Rng};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ulid::Ulid;
+use uuid::{Builder, Uuid};
+
+pub const NANOID_ALPHABET: &str =
+    "_-0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+// base62 of u32::MAX takes 6 chars
+const CHECKSUM_LENGTH: usize = 6;
+
+pub fn gen_uuid_v4() -> Uuid {
+    let mut rng = rand::thread_rng();
+    Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+pub fn gen_uuid_v7() -> Result<Uuid> {
+    let mut rng = rand::thread_rng();
+    Ok(Builder::from_unix_timestamp_millis(unix_millis()?, &rng.gen()).into_uuid())
+}
+
+pub fn gen_ulid() -> Result<Ulid> {
+    let mut rng = rand::thread_rng();
+    Ok(Ulid::from_parts(unix_millis()?, rng.gen()))
+}
+
+pub fn gen_nanoid(length: usize, alphabet: &str) -> Result<String> {
+    let alphabet: Vec<char> = alphabet.chars().collect();
+    if alphabet.len() < 2 {
+        bail!("alphabet must have at least 2 characters");
+    }
+    let mut rng = rand::thread_rng();
+    // choose() samples uniformly, no modulo bias
+    Ok((0..length)
+        .map(|_| *alphabet.choose(&mut rng).unwrap())
+        .collect())
+}
+
+pub fn gen_token(
+    length: usize,
+    encoding: TokenEncoding,
+    prefix: &str,
+    checksum: bool,
+) -> Result<String> {
+    let mut rng = rand::thread_rng();
+    let mut bytes = vec![0u8; length];
+    rng.fill(bytes.as_mut_slice());
+    let body = match encoding {
+        TokenEncoding::Hex => data_encoding::HEXLOWER.encode(&bytes),
+        TokenEncoding::Base64Url => base64_url_encode(&bytes),
+        TokenEncoding::Base58 => bs58::encode(&bytes).into_string(),
+    };
+    let mut token = format!("{prefix}{body}");
+    if checksum {
+        token.push_str(&token_checksum(&token));
+    }
+    Ok(token)
+}
+
+// checks the CRC32 tail added by `gen_token`, like GitHub does for its `ghp_` tokens
+pub fn verify_token(token: &str) -> bool {
+    if !token.is_ascii() || token.len() <= CHECKSUM_LENGTH {
+        return false;
+    }
+    let (body, checksum) = token.split_at(token.len() - CHECKSUM_LENGTH);
+    token_checksum(body) == checksum
+}
+
+fn token_checksum(body: &str) -> String {
+    let mut crc = crc32fast::hash(body.as_bytes());
+    let mut checksum = [b'0'; CHECKSUM_LENGTH];
+    for c in checksum.iter_mut().rev() {
+        *c = BASE62[(crc % 62) as usize];
+        crc /= 62;
+    }
+    String::from_utf8_lossy(&checksum).into_owned()
+}
+
+fn unix_millis() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_ids() {
+        let uuid = gen_uuid_v4();
+        assert_eq!(uuid.get_version_num(), 4);
+        let uuid = gen_uuid_v7().unwrap();
+        assert_eq!(uuid.get_version_num(), 7);
+        assert_eq!(gen_ulid().unwrap().to_string().len(), 26);
+
+        let id = gen_nanoid(21, NANOID_ALPHABET).unwrap();
+        assert_eq!(id.len(), 21);
+        let id = gen_nanoid(10, "ab").unwrap();
+        assert!(id.chars().all(|c| c == 'a' || c == 'b'));
+        assert!(gen_nanoid(10, "a").is_err());
+    }
+
+    #[test]
+    fn test_gen_token() {
+        let token = gen_token(32, TokenEncoding::Hex, "", false).unwrap();
+        assert_eq!(token.len(), 64);
+
+        for encoding in [
+            TokenEncoding::Hex,
+            TokenEncoding::Base64Url,
+            TokenEncoding::Base58,
+        ] {
+            let token = gen_token(20, encoding, "ghp_", true).unwrap();
+            assert!(token.starts_with("ghp_"));
+            assert!(verify_token(&token));
+        }
+
+        let token = gen_token(20, TokenEncoding::Base58, "ghp_", true).unwrap();
+        let mut tampered = token.into_bytes();
+        tampered[5] = if tampered[5] == b'x' { b'y' } else { b'x' };
+        assert!(!verify_token(&String::from_utf8(tampered).unwrap()));
+        assert!(!verify_token("abc"));
+    }
+}
@@ -1,5 +1,8 @@
-use anyhow::{Context, Result};
-use rand::{rngs::ThreadRng, seq::SliceRandom};
+use crate::PassOutputFormat;
+use anyhow::{bail, Result};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashSet;
 use zxcvbn::zxcvbn;
 
 const LOWER: &[u8] = b"abdefghjmnqrt";
@@ -14,37 +17,153 @@ pub fn gen_pass(
     no_number: bool,
     no_symbol: bool,
 ) -> Result<String> {
-    let mut pass = Vec::new();
     let mut rng = rand::thread_rng();
+    gen_pass_with_rng(&mut rng, length, no_lower, no_upper, no_number, no_symbol)
+}
+
+pub fn gen_pass_with_rng(
+    rng: &mut impl Rng,
+    length: u8,
+    no_lower: bool,
+    no_upper: bool,
+    no_number: bool,
+    no_symbol: bool,
+) -> Result<String> {
+    let mut pick = |n: usize| rng.gen_range(0..n);
+    gen_pass_with(&mut pick, length, no_lower, no_upper, no_number, no_symbol)
+}
+
+// pick(n) returns an index below n, every choice and the shuffle go through it
+pub fn gen_pass_with(
+    pick: &mut impl FnMut(usize) -> usize,
+    length: u8,
+    no_lower: bool,
+    no_upper: bool,
+    no_number: bool,
+    no_symbol: bool,
+) -> Result<String> {
+    let mut pass = Vec::new();
     let mut chars = Vec::new();
+    for (charset, skip) in [
+        (LOWER, no_lower),
+        (UPPER, no_upper),
+        (NUMBER, no_number),
+        (SYMBOL, no_symbol),
+    ] {
+        if !skip {
+            chars.extend_from_slice(charset);
+            pass.push(charset[pick(charset.len())]);
+        }
+    }
+    if chars.is_empty() {
+        bail!("no characters left to generate a password from");
+    }
+    for _ in pass.len()..length as usize {
+        pass.push(chars[pick(chars.len())]);
+    }
+    // fisher-yates, so the required characters don't always lead
+    for i in (1..pass.len()).rev() {
+        pass.swap(i, pick(i + 1));
+    }
+    Ok(String::from_utf8(pass)?)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PassRecord {
+    pub password: String,
+    pub length: usize,
+    pub entropy_bits: f64,
+    pub score: u8,
+}
+
+pub fn gen_pass_batch(
+    count: u32,
+    unique: bool,
+    length: u8,
+    no_lower: bool,
+    no_upper: bool,
+    no_number: bool,
+    no_symbol: bool,
+) -> Result<Vec<PassRecord>> {
+    let entropy_bits = pass_entropy(length, no_lower, no_upper, no_number, no_symbol);
+    if unique && (count as f64).log2() > entropy_bits {
+        bail!("cannot generate {count} unique passwords of length {length} with this charset");
+    }
+    let mut rng = rand::thread_rng();
+    let mut seen = HashSet::new();
+    let mut records = Vec::with_capacity(count as usize);
+    // collisions are only likely for tiny charsets, give up instead of looping forever
+    let mut attempts = count as u64 * 10 + 100;
+    while records.len() < count as usize {
+        if attempts == 0 {
+            bail!("too many duplicated passwords, try a longer length or a larger charset");
+        }
+        attempts -= 1;
+        let password =
+            gen_pass_with_rng(&mut rng, length, no_lower, no_upper, no_number, no_symbol)?;
+        if unique && !seen.insert(password.clone()) {
+            continue;
+        }
+        let score = pass_strength(&password)?;
+        records.push(PassRecord {
+            length: password.len(),
+            password,
+            entropy_bits,
+            score,
+        });
+    }
+    Ok(records)
+}
+
+pub fn pass_strength(pass: &str) -> Result<u8> {
+    Ok(zxcvbn(pass, &[])?.score())
+}
+
+pub fn pass_entropy(
+    length: u8,
+    no_lower: bool,
+    no_upper: bool,
+    no_number: bool,
+    no_symbol: bool,
+) -> f64 {
+    let mut charset = 0;
     if !no_lower {
-        chars.extend_from_slice(LOWER);
-        pass.push(choose(LOWER, &mut rng)?);
+        charset += LOWER.len();
     }
     if !no_upper {
-        chars.extend(UPPER);
-        pass.push(choose(UPPER, &mut rng)?);
+        charset += UPPER.len();
     }
     if !no_number {
-        chars.extend(NUMBER);
-        pass.push(choose(NUMBER, &mut rng)?);
+        charset += NUMBER.len();
     }
     if !no_symbol {
-        chars.extend(SYMBOL);
-        pass.push(choose(SYMBOL, &mut rng)?);
+        charset += SYMBOL.len();
     }
-    for _ in pass.len()..length as usize {
-        pass.push(choose(&chars, &mut rng)?);
+    if charset == 0 {
+        return 0.0;
     }
-    pass.shuffle(&mut rng);
-    let pass = String::from_utf8(pass)?;
-    let estimate = zxcvbn(&pass, &[])?;
-    eprintln!("pass strength: {}", estimate.score());
-    Ok(pass)
+    // round to 2 decimals, it is an estimate anyway
+    let bits = length as f64 * (charset as f64).log2();
+    (bits * 100.0).round() / 100.0
 }
 
-fn choose(chars: &[u8], rng: &mut ThreadRng) -> Result<u8> {
-    Ok(*chars.choose(rng).context("")?)
+pub fn pass_output(records: &[PassRecord], format: PassOutputFormat) -> Result<String> {
+    let content = match format {
+        PassOutputFormat::Text => records
+            .iter()
+            .map(|r| r.password.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        PassOutputFormat::Json => serde_json::to_string_pretty(records)?,
+        PassOutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                wtr.serialize(record)?;
+            }
+            String::from_utf8(wtr.into_inner()?)?.trim_end().to_string()
+        }
+    };
+    Ok(content)
 }
 
 #[cfg(test)]
@@ -97,4 +216,24 @@ mod tests {
         let pass = gen_pass(17, false, false, false, true);
         expect_pass(pass.as_ref().unwrap(), 17, false, false, false, true);
     }
+
+    #[test]
+    fn test_gen_pass_batch() {
+        let records = gen_pass_batch(20, true, 12, false, false, false, false).unwrap();
+        assert_eq!(records.len(), 20);
+        let unique: HashSet<_> = records.iter().map(|r| &r.password).collect();
+        assert_eq!(unique.len(), 20);
+        assert!(records.iter().all(|r| r.length == 12 && r.score <= 4));
+
+        // 10 digits with length 1 can never give 11 unique passwords
+        assert!(gen_pass_batch(11, true, 1, true, true, false, true).is_err());
+        assert_eq!(pass_entropy(1, true, true, false, true), 3.32);
+
+        let csv = pass_output(&records[..2], PassOutputFormat::Csv).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.starts_with("password,length,entropy_bits,score"));
+        let json = pass_output(&records, PassOutputFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 20);
+    }
 }
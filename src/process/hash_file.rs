@@ -0,0 +1,278 @@
+use crate::{get_reader, HashAlg};
+use anyhow::{anyhow, bail, Result};
+use data_encoding::HEXLOWER;
+use rayon::prelude::*;
+use sha2::Digest;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+const BUF_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Missing,
+    // on disk under a checked path but not listed in the manifest
+    New,
+}
+
+#[derive(Debug)]
+pub struct CheckEntry {
+    pub path: PathBuf,
+    pub status: CheckStatus,
+}
+
+pub fn hash_reader(reader: &mut dyn Read, alg: HashAlg) -> Result<String> {
+    let hash = match alg {
+        HashAlg::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            read_chunks(reader, |chunk| {
+                hasher.update(chunk);
+            })?;
+            hasher.finalize().to_hex().to_string()
+        }
+        HashAlg::Sha256 => digest_reader::<sha2::Sha256>(reader)?,
+        HashAlg::Sha512 => digest_reader::<sha2::Sha512>(reader)?,
+        HashAlg::Sha1 => digest_reader::<sha1::Sha1>(reader)?,
+        HashAlg::Md5 => digest_reader::<md5::Md5>(reader)?,
+        HashAlg::Crc32 => {
+            let mut hasher = crc32fast::Hasher::new();
+            read_chunks(reader, |chunk| hasher.update(chunk))?;
+            format!("{:08x}", hasher.finalize())
+        }
+    };
+    Ok(hash)
+}
+
+// "-" is stdin, files are hashed with blake3's multithreaded mode when it applies
+pub fn hash_path(path: &str, alg: HashAlg) -> Result<String> {
+    if let (HashAlg::Blake3, false) = (alg, path == "-") {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_mmap_rayon(path)?;
+        return Ok(hasher.finalize().to_hex().to_string());
+    }
+    let mut reader = get_reader(path)?;
+    hash_reader(&mut reader, alg)
+}
+
+// hashes files in parallel, results keep the order of the input
+pub fn hash_files(files: &[PathBuf], alg: HashAlg) -> Vec<Result<String>> {
+    files
+        .par_iter()
+        .map(|file| hash_path(&file.to_string_lossy(), alg))
+        .collect()
+}
+
+// expands directories into the files below them, sorted so output is stable
+pub fn collect_files(paths: &[String], recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let p = Path::new(path);
+        if p.is_dir() {
+            if !recursive {
+                bail!("{} is a directory, use --recursive to hash its files", path);
+            }
+            walk_dir(p, &mut files)?;
+        } else {
+            files.push(p.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            walk_dir(&entry, files)?;
+        } else {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// one line per file in the format of sha256sum and b3sum
+pub fn manifest_line(hash: &str, path: &Path) -> String {
+    format!("{}  {}", hash, path.display())
+}
+
+pub fn parse_manifest(content: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // "<hash>  <path>" for text mode, "<hash> *<path>" for binary mode
+        let (hash, path) = line
+            .split_once(' ')
+            .and_then(|(hash, rest)| {
+                let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+                Some((hash, path))
+            })
+            .ok_or_else(|| anyhow!("invalid manifest line {}: {:?}", i + 1, line))?;
+        if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("invalid hash on manifest line {}", i + 1);
+        }
+        entries.push((hash.to_ascii_lowercase(), PathBuf::from(path)));
+    }
+    Ok(entries)
+}
+
+// verifies the manifest entries, and reports files found under `paths` that it doesn't list
+pub fn check_manifest(
+    entries: &[(String, PathBuf)],
+    alg: HashAlg,
+    paths: &[String],
+) -> Result<Vec<CheckEntry>> {
+    if let Some((hash, path)) = entries.iter().find(|(h, _)| h.len() != alg.hex_len()) {
+        bail!(
+            "{} has a {} char hash, {} hashes are {} chars, pick the algorithm with --alg",
+            path.display(),
+            hash.len(),
+            alg,
+            alg.hex_len()
+        );
+    }
+    let mut checked: Vec<CheckEntry> = entries
+        .par_iter()
+        .map(|(expected, path)| {
+            let status = if !path.exists() {
+                CheckStatus::Missing
+            } else {
+                match hash_path(&path.to_string_lossy(), alg) {
+                    Ok(hash) if &hash == expected => CheckStatus::Ok,
+                    _ => CheckStatus::Failed,
+                }
+            };
+            CheckEntry {
+                path: path.clone(),
+                status,
+            }
+        })
+        .collect();
+
+    let listed: HashSet<_> = entries.iter().map(|(_, p)| normalize(p)).collect();
+    for file in collect_files(paths, true)? {
+        if !listed.contains(&normalize(&file)) {
+            checked.push(CheckEntry {
+                path: file,
+                status: CheckStatus::New,
+            });
+        }
+    }
+    Ok(checked)
+}
+
+// "./a/b" and "a/b" name the same file
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
+
+fn read_chunks(reader: &mut dyn Read, mut f: impl FnMut(&[u8])) -> Result<()> {
+    let mut buf = vec![0u8; BUF_SIZE];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n]);
+    }
+}
+
+fn digest_reader<D: Digest>(reader: &mut dyn Read) -> Result<String> {
+    let mut hasher = D::new();
+    read_chunks(reader, |chunk| hasher.update(chunk))?;
+    Ok(HEXLOWER.encode(&hasher.finalize()))
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "OK"),
+            CheckStatus::Failed => write!(f, "FAILED"),
+            CheckStatus::Missing => write!(f, "MISSING"),
+            CheckStatus::New => write!(f, "NEW"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_reader() {
+        let hash = |alg| hash_reader(&mut &b"abc"[..], alg).unwrap();
+        assert_eq!(
+            hash(HashAlg::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash(HashAlg::Sha1),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hash(HashAlg::Md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hash(HashAlg::Crc32), "352441c2");
+        assert_eq!(
+            hash(HashAlg::Blake3),
+            blake3::hash(b"abc").to_hex().as_str()
+        );
+        for alg in [HashAlg::Sha512, HashAlg::Blake3, HashAlg::Crc32] {
+            assert_eq!(hash(alg).len(), alg.hex_len());
+        }
+        // the multithreaded file path agrees with the streaming one
+        let data = fs::read("Cargo.toml").unwrap();
+        assert_eq!(
+            hash_path("Cargo.toml", HashAlg::Blake3).unwrap(),
+            blake3::hash(&data).to_hex().as_str()
+        );
+    }
+
+    #[test]
+    fn test_check_manifest() {
+        let files = collect_files(&["fixtures".to_string()], true).unwrap();
+        assert!(files.len() > 1);
+        let hashes = hash_files(&files, HashAlg::Sha256);
+        let mut manifest: Vec<String> = files
+            .iter()
+            .zip(hashes)
+            .map(|(f, h)| manifest_line(&h.unwrap(), f))
+            .collect();
+        // drop one file to see it reported as new, tamper with another and add a missing one
+        manifest.remove(0);
+        manifest[0] = manifest_line(&"0".repeat(64), &files[1]);
+        manifest.push(manifest_line(
+            &"0".repeat(64),
+            Path::new("fixtures/nonexistent"),
+        ));
+        let entries = parse_manifest(&manifest.join("\n")).unwrap();
+        let checked =
+            check_manifest(&entries, HashAlg::Sha256, &["./fixtures".to_string()]).unwrap();
+        let count = |status| checked.iter().filter(|e| e.status == status).count();
+        assert_eq!(count(CheckStatus::Failed), 1);
+        assert_eq!(count(CheckStatus::Missing), 1);
+        assert_eq!(count(CheckStatus::New), 1);
+        assert_eq!(count(CheckStatus::Ok), files.len() - 2);
+
+        assert!(check_manifest(&entries, HashAlg::Md5, &[]).is_err());
+        assert!(parse_manifest("nohash").is_err());
+        let entries = parse_manifest("ABCD *bin/file\n# comment\n").unwrap();
+        assert_eq!(
+            entries,
+            vec![("abcd".to_string(), PathBuf::from("bin/file"))]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{PassHashAlg, PassHashCost};
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, Error as PasswordHashError, PasswordHash, PasswordHasher,
+        PasswordVerifier, SaltString,
+    },
+    Algorithm, Argon2, Params as Argon2Params, Version,
+};
+use pbkdf2::{Params as Pbkdf2Params, Pbkdf2};
+use scrypt::{Params as ScryptParams, Scrypt};
+
+pub fn pass_hash(password: &str, alg: PassHashAlg, cost: &PassHashCost) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password = password.as_bytes();
+    let hash = match alg {
+        PassHashAlg::Argon2id | PassHashAlg::Argon2i | PassHashAlg::Argon2d => {
+            let alg = match alg {
+                PassHashAlg::Argon2i => Algorithm::Argon2i,
+                PassHashAlg::Argon2d => Algorithm::Argon2d,
+                _ => Algorithm::Argon2id,
+            };
+            let params = Argon2Params::new(
+                cost.m_cost.unwrap_or(Argon2Params::DEFAULT_M_COST),
+                cost.t_cost.unwrap_or(Argon2Params::DEFAULT_T_COST),
+                cost.p_cost.unwrap_or(Argon2Params::DEFAULT_P_COST),
+                None,
+            )
+            .map_err(|e| anyhow!("invalid argon2 params: {e}"))?;
+            Argon2::new(alg, Version::V0x13, params)
+                .hash_password(password, &salt)
+                .map_err(hash_error)?
+                .to_string()
+        }
+        PassHashAlg::Bcrypt => {
+            bcrypt::hash(password, cost.bcrypt_cost.unwrap_or(bcrypt::DEFAULT_COST))?
+        }
+        PassHashAlg::Scrypt => {
+            let params = ScryptParams::new(
+                cost.log_n.unwrap_or(ScryptParams::RECOMMENDED_LOG_N),
+                cost.scrypt_r.unwrap_or(ScryptParams::RECOMMENDED_R),
+                cost.scrypt_p.unwrap_or(ScryptParams::RECOMMENDED_P),
+                ScryptParams::RECOMMENDED_LEN,
+            )
+            .map_err(|e| anyhow!("invalid scrypt params: {e}"))?;
+            Scrypt
+                .hash_password_customized(password, None, None, params, &salt)
+                .map_err(hash_error)?
+                .to_string()
+        }
+        PassHashAlg::Pbkdf2 => {
+            let params = Pbkdf2Params {
+                rounds: cost.rounds.unwrap_or(Pbkdf2Params::default().rounds),
+                ..Default::default()
+            };
+            Pbkdf2
+                .hash_password_customized(password, None, None, params, &salt)
+                .map_err(hash_error)?
+                .to_string()
+        }
+    };
+    Ok(hash)
+}
+
+pub fn pass_hash_detect(hash: &str) -> Result<PassHashAlg> {
+    let alg = match hash.split('$').nth(1) {
+        Some("argon2id") => PassHashAlg::Argon2id,
+        Some("argon2i") => PassHashAlg::Argon2i,
+        Some("argon2d") => PassHashAlg::Argon2d,
+        Some("2a" | "2b" | "2x" | "2y") => PassHashAlg::Bcrypt,
+        Some("scrypt") => PassHashAlg::Scrypt,
+        Some("pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512") => PassHashAlg::Pbkdf2,
+        _ => return Err(anyhow!("unknown password hash format")),
+    };
+    Ok(alg)
+}
+
+pub fn pass_verify(password: &str, hash: &str) -> Result<bool> {
+    let password = password.as_bytes();
+    let alg = pass_hash_detect(hash)?;
+    if let PassHashAlg::Bcrypt = alg {
+        return Ok(bcrypt::verify(password, hash)?);
+    }
+    let parsed = PasswordHash::new(hash).map_err(hash_error)?;
+    // cost params, and the argon2 variant, are read back from the hash itself
+    let res = match alg {
+        PassHashAlg::Argon2id | PassHashAlg::Argon2i | PassHashAlg::Argon2d => {
+            Argon2::default().verify_password(password, &parsed)
+        }
+        PassHashAlg::Scrypt => Scrypt.verify_password(password, &parsed),
+        PassHashAlg::Pbkdf2 => Pbkdf2.verify_password(password, &parsed),
+        PassHashAlg::Bcrypt => unreachable!(),
+    };
+    match res {
+        Ok(()) => Ok(true),
+        Err(PasswordHashError::Password) => Ok(false),
+        Err(e) => Err(hash_error(e)),
+    }
+}
+
+fn hash_error(e: PasswordHashError) -> anyhow::Error {
+    anyhow!("password hash error: {e}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_hash_verify() {
+        // keep costs low, the defaults are far too slow for debug builds
+        let cost = PassHashCost {
+            m_cost: Some(1024),
+            t_cost: Some(1),
+            p_cost: Some(1),
+            bcrypt_cost: Some(4),
+            log_n: Some(8),
+            scrypt_r: Some(8),
+            scrypt_p: Some(1),
+            rounds: Some(1000),
+        };
+        for alg in [
+            PassHashAlg::Argon2id,
+            PassHashAlg::Argon2i,
+            PassHashAlg::Argon2d,
+            PassHashAlg::Bcrypt,
+            PassHashAlg::Scrypt,
+            PassHashAlg::Pbkdf2,
+        ] {
+            let hash = pass_hash("hunter2", alg, &cost).unwrap();
+            assert_eq!(
+                pass_hash_detect(&hash).unwrap().to_string(),
+                alg.to_string()
+            );
+            assert!(pass_verify("hunter2", &hash).unwrap());
+            assert!(!pass_verify("hunter3", &hash).unwrap());
+        }
+        assert!(pass_verify("hunter2", "$md5$abc").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{key_decode, key_format_detect, write_private_file, KeyKind, KeyTrust};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+
+const KEYRING_VERSION: u32 = 1;
+const KEYRING_INDEX: &str = "keyring.json";
+// overrides ~/.config/rcli/keys, for scripts and tests
+pub const KEYRING_ENV: &str = "RCLI_KEYRING";
+// hex digits of a fingerprint that are enough to address a key
+const FINGERPRINT_MIN_PREFIX: usize = 8;
+pub const FINGERPRINT_SHORT_LEN: usize = 16;
+
+// a directory of named keys, each stored as imported, and an index of what they are
+#[derive(Debug)]
+pub struct Keyring {
+    dir: PathBuf,
+    pub keys: Vec<KeyringEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyringEntry {
+    pub name: String,
+    pub kind: KeyKind,
+    pub fingerprint: String,
+    pub format: String,
+    pub trust: KeyTrust,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub added: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyringIndex {
+    version: u32,
+    keys: Vec<KeyringEntry>,
+}
+
+impl KeyringEntry {
+    pub fn short_fingerprint(&self) -> &str {
+        &self.fingerprint[..FINGERPRINT_SHORT_LEN.min(self.fingerprint.len())]
+    }
+
+    // a signing key can stand in for its public half
+    pub fn supplies(&self, kind: KeyKind) -> bool {
+        self.kind == kind || (self.kind, kind) == (KeyKind::Ed25519Sign, KeyKind::Ed25519Verify)
+    }
+}
+
+impl Keyring {
+    pub fn default_dir() -> Result<PathBuf> {
+        if let Some(dir) = std::env::var_os(KEYRING_ENV) {
+            return Ok(dir.into());
+        }
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::var_os("HOME")
+                .map(|home| Path::new(&home).join(".config"))
+                .ok_or_else(|| anyhow!("no home directory, set {}", KEYRING_ENV))?,
+        };
+        Ok(config.join("rcli").join("keys"))
+    }
+
+    pub fn open() -> Result<Self> {
+        Self::open_in(Self::default_dir()?)
+    }
+
+    // a keyring that doesn't exist yet is empty
+    pub fn open_in(dir: PathBuf) -> Result<Self> {
+        let index = dir.join(KEYRING_INDEX);
+        if !index.exists() {
+            return Ok(Self { dir, keys: vec![] });
+        }
+        let index: KeyringIndex = serde_json::from_str(&fs::read_to_string(&index)?)
+            .map_err(|e| anyhow!("{} is not a keyring index: {}", index.display(), e))?;
+        if index.version != KEYRING_VERSION {
+            bail!("unsupported keyring version {}", index.version);
+        }
+        Ok(Self {
+            dir,
+            keys: index.keys,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, entry: &KeyringEntry) -> PathBuf {
+        self.dir.join(format!("{}.key", entry.name))
+    }
+
+    // by name first, then by a unique fingerprint prefix, both halves of a key pair share
+    // their fingerprint so the kind wanted picks between them
+    pub fn find(&self, query: &str, kind: Option<KeyKind>) -> Result<&KeyringEntry> {
+        let query = query.strip_prefix('@').unwrap_or(query);
+        if let Some(entry) = self.keys.iter().find(|e| e.name == query) {
+            // a raw public key would load as a secret one, only a verify may take any kind
+            if let Some(kind) = kind.filter(|k| *k != KeyKind::Ed25519Verify) {
+                if !entry.supplies(kind) {
+                    bail!("key @{} is {}, expected {}", query, entry.kind, kind);
+                }
+            }
+            return Ok(entry);
+        }
+        let prefix = query.to_ascii_lowercase();
+        let mut matches: Vec<_> = match prefix.len() >= FINGERPRINT_MIN_PREFIX {
+            true => self
+                .keys
+                .iter()
+                .filter(|e| e.fingerprint.starts_with(&prefix))
+                .collect(),
+            false => vec![],
+        };
+        if let Some(kind) = kind {
+            if matches.iter().any(|e| e.supplies(kind)) {
+                matches.retain(|e| e.supplies(kind));
+            }
+            if matches.iter().any(|e| e.kind == kind) {
+                matches.retain(|e| e.kind == kind);
+            }
+        }
+        match matches.as_slice() {
+            [entry] => Ok(entry),
+            [] => bail!(
+                "no key named or with fingerprint {} in {}",
+                query,
+                self.dir.display()
+            ),
+            _ => bail!(
+                "fingerprint {} matches several keys: {}",
+                query,
+                matches
+                    .iter()
+                    .map(|e| e.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    // the public half of a key pair first, it's what the signature was checked with
+    pub fn find_fingerprint(&self, fingerprint: &str) -> Option<&KeyringEntry> {
+        self.keys
+            .iter()
+            .filter(|e| e.fingerprint == fingerprint)
+            .min_by_key(|e| e.kind != KeyKind::Ed25519Verify)
+    }
+
+    // keeps the key file as it is, so a keystore stays sealed
+    pub fn import(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        kind: Option<KeyKind>,
+        trust: KeyTrust,
+        comment: Option<String>,
+        force: bool,
+    ) -> Result<&KeyringEntry> {
+        key_name_check(name)?;
+        if !force && self.keys.iter().any(|e| e.name == name) {
+            bail!("a key named {} is already in the keyring", name);
+        }
+        let format = key_format_detect(data);
+        let (kind, fingerprint) = key_identify(data, kind)?;
+        let entry = KeyringEntry {
+            name: name.to_string(),
+            kind,
+            fingerprint,
+            format: format.to_string(),
+            trust,
+            comment,
+            added: OffsetDateTime::now_utc(),
+        };
+        keyring_dir_create(&self.dir)?;
+        let path = self.path(&entry);
+        match kind {
+            KeyKind::Ed25519Verify => fs::write(&path, data)?,
+            _ => write_private_file(&path, data)?,
+        }
+        self.keys.retain(|e| e.name != name);
+        self.keys.push(entry);
+        self.save()?;
+        Ok(self.keys.last().expect("the key was just added"))
+    }
+
+    pub fn delete(&mut self, query: &str) -> Result<KeyringEntry> {
+        let entry = self.find(query, None)?.clone();
+        let path = self.path(&entry);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        self.keys.retain(|e| e.name != entry.name);
+        self.save()?;
+        Ok(entry)
+    }
+
+    fn save(&self) -> Result<()> {
+        let index = KeyringIndex {
+            version: KEYRING_VERSION,
+            keys: self.keys.clone(),
+        };
+        let json = serde_json::to_string_pretty(&index)? + "\n";
+        fs::write(self.dir.join(KEYRING_INDEX), json)?;
+        Ok(())
+    }
+}
+
+// an @name or @fingerprint key argument becomes the path of the key in the keyring
+pub fn keyring_resolve(key: &str, kind: KeyKind) -> Result<String> {
+    if !key.starts_with('@') {
+        return Ok(key.to_string());
+    }
+    let keyring = Keyring::open()?;
+    let path = keyring.path(keyring.find(key, Some(kind))?);
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// a keystore has to be opened, the public key it shows isn't trusted
+fn key_identify(data: &[u8], kind: Option<KeyKind>) -> Result<(KeyKind, String)> {
+    let key = key_decode(data, kind)?;
+    Ok((key.kind(), key.fingerprint()))
+}
+
+// names end up as file names, and @ arguments shouldn't need quoting
+fn key_name_check(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !valid {
+        bail!(
+            "invalid key name {:?}, use letters, digits, '.', '-' and '_'",
+            name
+        );
+    }
+    Ok(())
+}
+
+fn keyring_dir_create(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring() {
+        let dir = std::env::temp_dir().join(format!("rcli-keyring-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut keyring = Keyring::open_in(dir.clone()).unwrap();
+        let verify = fs::read("fixtures/ed25519.verify").unwrap();
+        let sign = fs::read("fixtures/ed25519.sign").unwrap();
+        let blake3 = fs::read("fixtures/blake3.txt").unwrap();
+        let kind = Some(KeyKind::Ed25519Verify);
+        keyring
+            .import(
+                "alice",
+                &verify,
+                kind,
+                KeyTrust::Full,
+                Some("laptop".into()),
+                false,
+            )
+            .unwrap();
+        let kind = Some(KeyKind::Ed25519Sign);
+        keyring
+            .import("alice-sign", &sign, kind, KeyTrust::Unknown, None, false)
+            .unwrap();
+        keyring
+            .import(
+                "shared",
+                &blake3,
+                Some(KeyKind::Blake3),
+                KeyTrust::Unknown,
+                None,
+                false,
+            )
+            .unwrap();
+        assert!(keyring
+            .import("alice", &verify, kind, KeyTrust::Full, None, false)
+            .is_err());
+        assert!(keyring
+            .import("../x", &verify, kind, KeyTrust::Full, None, false)
+            .is_err());
+
+        let keyring = Keyring::open_in(dir.clone()).unwrap();
+        let alice = keyring.find("@alice", None).unwrap();
+        assert_eq!(alice.kind, KeyKind::Ed25519Verify);
+        assert_eq!(alice.trust, KeyTrust::Full);
+        assert_eq!(alice.comment.as_deref(), Some("laptop"));
+        let index = fs::read_to_string(dir.join(KEYRING_INDEX)).unwrap();
+        assert!(index.contains(r#""kind": "ed25519-verify""#));
+        assert!(index.contains(r#""trust": "full""#));
+        // the signing key has the fingerprint of its public half, the kind picks one
+        assert_eq!(
+            keyring.find("alice-sign", None).unwrap().fingerprint,
+            alice.fingerprint
+        );
+        let fingerprint = alice.short_fingerprint();
+        assert!(keyring.find(fingerprint, None).is_err());
+        let find = |kind| keyring.find(fingerprint, Some(kind)).unwrap().name.as_str();
+        assert_eq!(find(KeyKind::Ed25519Verify), "alice");
+        assert_eq!(find(KeyKind::Ed25519Sign), "alice-sign");
+        let shared = keyring.find("shared", None).unwrap();
+        assert_eq!(
+            keyring.find(&shared.fingerprint[..8], None).unwrap().name,
+            "shared"
+        );
+        assert!(keyring.find("nobody", None).is_err());
+        assert!(keyring.find("alice", Some(KeyKind::Ed25519Sign)).is_err());
+        assert!(keyring.find("shared", Some(KeyKind::Ed25519Sign)).is_err());
+        assert!(keyring.find("alice-sign", Some(KeyKind::Blake3)).is_err());
+
+        let mut keyring = keyring;
+        keyring.delete("shared").unwrap();
+        assert!(!dir.join("shared.key").exists());
+        assert_eq!(Keyring::open_in(dir.clone()).unwrap().keys.len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    base64_decode, base64_encode, chacha_open_aad, chacha_seal_aad, get_content,
+    write_private_file, KeyKind, KeyMaterial,
+};
+use anyhow::{anyhow, bail, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use data_encoding::HEXLOWER;
+use ed25519_dalek::SigningKey;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const KEYSTORE_VERSION: u32 = 1;
+const KEYSTORE_KDF: &str = "argon2id";
+const KEYSTORE_CIPHER: &str = "chacha20-poly1305";
+// a keystore says how hard it is to open, don't let one ask for more than this
+const KEYSTORE_MAX_M_COST: u32 = 1024 * 1024;
+const KEYSTORE_MAX_T_COST: u32 = 16;
+const KEYSTORE_MAX_P_COST: u32 = 16;
+
+// a secret key sealed with a passphrase, written as JSON so it says how to open it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub kind: String,
+    // the ed25519 public key, for people reading the file, only trusted once opened
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    pub kdf: KeystoreKdf,
+    pub cipher: String,
+    // nonce and ciphertext, base64, sealed with the header as associated data
+    pub ciphertext: String,
+}
+
+// everything but the ciphertext, as the cipher authenticates it
+#[derive(Serialize)]
+struct KeystoreHeader<'a> {
+    version: u32,
+    kind: &'a str,
+    public_key: Option<&'a str>,
+    kdf: &'a KeystoreKdf,
+    cipher: &'a str,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeystoreKdf {
+    pub alg: String,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub salt: String,
+}
+
+impl KeystoreKdf {
+    pub fn new(m_cost: u32, t_cost: u32, p_cost: u32) -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            alg: KEYSTORE_KDF.to_string(),
+            m_cost,
+            t_cost,
+            p_cost,
+            salt: base64_encode(salt),
+        }
+    }
+
+    // the same costs with a fresh salt
+    pub fn renew(&self) -> Self {
+        Self::new(self.m_cost, self.t_cost, self.p_cost)
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<Zeroizing<[u8; 32]>> {
+        if self.alg != KEYSTORE_KDF {
+            bail!("unsupported keystore kdf {:?}", self.alg);
+        }
+        if self.m_cost > KEYSTORE_MAX_M_COST
+            || self.t_cost > KEYSTORE_MAX_T_COST
+            || self.p_cost > KEYSTORE_MAX_P_COST
+        {
+            bail!(
+                "argon2 costs m={} t={} p={} are above the limits m={} t={} p={}",
+                self.m_cost,
+                self.t_cost,
+                self.p_cost,
+                KEYSTORE_MAX_M_COST,
+                KEYSTORE_MAX_T_COST,
+                KEYSTORE_MAX_P_COST
+            );
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| anyhow!("invalid argon2 parameters: {e}"))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(
+                passphrase.as_bytes(),
+                &base64_decode(&self.salt)?,
+                key.as_mut(),
+            )
+            .map_err(|e| anyhow!("argon2 failed: {e}"))?;
+        Ok(key)
+    }
+}
+
+// 64 MiB and 3 passes, a second or so to open
+impl Default for KeystoreKdf {
+    fn default() -> Self {
+        Self::new(64 * 1024, 3, 1)
+    }
+}
+
+impl Keystore {
+    pub fn seal(key: &KeyMaterial, passphrase: &str, kdf: KeystoreKdf) -> Result<Self> {
+        if passphrase.is_empty() {
+            bail!("the passphrase can't be empty");
+        }
+        let public_key = match key {
+            KeyMaterial::Blake3(_) => None,
+            KeyMaterial::Ed25519Sign(key) => Some(HEXLOWER.encode(key.verifying_key().as_bytes())),
+            KeyMaterial::Ed25519Verify(_) => bail!("public keys don't need a passphrase"),
+        };
+        let mut store = Self {
+            version: KEYSTORE_VERSION,
+            kind: key.kind().to_string(),
+            public_key,
+            kdf,
+            cipher: KEYSTORE_CIPHER.to_string(),
+            ciphertext: String::new(),
+        };
+        let secret = Zeroizing::new(key.to_bytes());
+        let ciphertext = chacha_seal_aad(
+            secret.as_ref(),
+            store.kdf.derive(passphrase)?.as_ref(),
+            &store.header()?,
+        )?;
+        store.ciphertext = base64_encode(ciphertext);
+        Ok(store)
+    }
+
+    pub fn open(&self, passphrase: &str) -> Result<KeyMaterial> {
+        if self.cipher != KEYSTORE_CIPHER {
+            bail!("unsupported keystore cipher {:?}", self.cipher);
+        }
+        let key = self.kdf.derive(passphrase)?;
+        let secret = chacha_open_aad(
+            &base64_decode(&self.ciphertext)?,
+            key.as_ref(),
+            &self.header()?,
+        )
+        .map_err(|_| anyhow!("wrong passphrase or corrupted keystore"))?;
+        let secret = Zeroizing::new(secret);
+        let secret: Zeroizing<[u8; 32]> = Zeroizing::new(
+            secret
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("keystore holds a key of {} bytes", secret.len()))?,
+        );
+        let key = match self.kind()? {
+            KeyKind::Blake3 => KeyMaterial::Blake3(secret),
+            KeyKind::Ed25519Sign => KeyMaterial::Ed25519Sign(SigningKey::from_bytes(&secret)),
+            KeyKind::Ed25519Verify => bail!("keystore holds a public key"),
+        };
+        if let (Some(public), KeyMaterial::Ed25519Sign(key)) = (&self.public_key, &key) {
+            if *public != HEXLOWER.encode(key.verifying_key().as_bytes()) {
+                bail!("keystore public key doesn't belong to its secret key");
+            }
+        }
+        Ok(key)
+    }
+
+    pub fn kind(&self) -> Result<KeyKind> {
+        self.kind
+            .parse()
+            .map_err(|_| anyhow!("unknown keystore key kind {:?}", self.kind))
+    }
+
+    fn header(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&KeystoreHeader {
+            version: self.version,
+            kind: &self.kind,
+            public_key: self.public_key.as_deref(),
+            kdf: &self.kdf,
+            cipher: &self.cipher,
+        })?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let store: Self = serde_json::from_str(content)?;
+        if store.version != KEYSTORE_VERSION {
+            bail!("unsupported keystore version {}", store.version);
+        }
+        Ok(store)
+    }
+
+    pub fn read(path: &str) -> Result<Self> {
+        Self::parse(&get_content(path)?).map_err(|e| anyhow!("{} is not a keystore: {}", path, e))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write_private_file(path, self.to_json()?.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+
+    #[test]
+    fn test_keystore() {
+        // cheap argon2 costs, the real ones are slow in debug builds
+        let kdf = || KeystoreKdf::new(64, 1, 1);
+        let key = KeyMaterial::Ed25519Sign(SigningKey::from_bytes(&[7; 32]));
+        let store = Keystore::seal(&key, "correct horse", kdf()).unwrap();
+        let store = Keystore::parse(&serde_json::to_string(&store).unwrap()).unwrap();
+        assert_eq!(store.kind, "ed25519-sign");
+        assert_eq!(
+            store.open("correct horse").unwrap().to_bytes(),
+            key.to_bytes()
+        );
+        assert!(store.open("wrong horse").is_err());
+
+        // the header is bound to the sealed key, another public key with a signature that
+        // covers it, as if the store vouched for it, still doesn't open
+        let other = SigningKey::from_bytes(&[8; 32]);
+        let mut forged = Keystore::parse(&store.to_json().unwrap()).unwrap();
+        forged.public_key = Some(HEXLOWER.encode(other.verifying_key().as_bytes()));
+        let signed = [
+            forged.header().unwrap(),
+            forged.ciphertext.clone().into_bytes(),
+        ]
+        .concat();
+        let mut forged: serde_json::Value =
+            serde_json::from_str(&forged.to_json().unwrap()).unwrap();
+        forged["public_key_sig"] = HEXLOWER.encode(&other.sign(&signed).to_bytes()).into();
+        let forged = Keystore::parse(&forged.to_string()).unwrap();
+        assert!(forged.open("correct horse").is_err());
+        let mut costly = Keystore::parse(&store.to_json().unwrap()).unwrap();
+        costly.kdf.m_cost = 65;
+        assert!(costly.open("correct horse").is_err());
+        costly.kdf.m_cost = u32::MAX;
+        assert!(costly.open("correct horse").is_err());
+
+        let key = KeyMaterial::Blake3(Zeroizing::new([9; 32]));
+        let store = Keystore::seal(&key, "pw", kdf()).unwrap();
+        assert!(store.public_key.is_none());
+        assert_eq!(store.open("pw").unwrap().to_bytes(), [9; 32]);
+        assert!(Keystore::seal(&key, "", kdf()).is_err());
+    }
+}
@@ -0,0 +1,557 @@
+use crate::{base64_decode, base64_encode, read_passphrase, TextSign, TextVerify, PASSPHRASE_ENV};
+use anyhow::{anyhow, bail, Context, Result};
+use blake2::{digest::consts::U32, Blake2b, Blake2b512};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha512};
+use std::{
+    io::Read,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+const UNTRUSTED_PREFIX: &str = "untrusted comment: ";
+const TRUSTED_PREFIX: &str = "trusted comment: ";
+
+const SIG_ALG: &[u8; 2] = b"Ed";
+// minisign signs a BLAKE2b-512 digest of the file instead of the file itself
+const SIG_ALG_HASHED: &[u8; 2] = b"ED";
+const MINISIGN_KDF_ALG: &[u8; 2] = b"Sc";
+const MINISIGN_CHK_ALG: &[u8; 2] = b"B2";
+const SIGNIFY_KDF_ALG: &[u8; 2] = b"BK";
+
+// libsodium's scryptsalsa208sha256 "sensitive" limits, what minisign -G uses
+const MINISIGN_OPSLIMIT: u64 = 33_554_432;
+const MINISIGN_MEMLIMIT: u64 = 1_073_741_824;
+const SIGNIFY_KDF_ROUNDS: u32 = 42;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignifyFlavor {
+    Minisign,
+    Signify,
+}
+
+// both tools tie keys and signatures together with a random 8 byte key number
+pub struct SignifyPublicKey {
+    pub flavor: SignifyFlavor,
+    pub key_id: [u8; 8],
+    pub key: VerifyingKey,
+}
+
+pub struct SignifySecretKey {
+    pub flavor: SignifyFlavor,
+    pub key_id: [u8; 8],
+    pub key: SigningKey,
+}
+
+pub struct SignifySigner {
+    key: SignifySecretKey,
+    comment: Option<String>,
+}
+
+pub struct SignifyVerifier {
+    key: SignifyPublicKey,
+}
+
+impl SignifyFlavor {
+    fn name(&self) -> &'static str {
+        match self {
+            SignifyFlavor::Minisign => "minisign",
+            SignifyFlavor::Signify => "signify",
+        }
+    }
+}
+
+impl SignifyPublicKey {
+    pub fn load(flavor: SignifyFlavor, path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        // minisign -P style keys are just the base64 line
+        let data = match content.trim().lines().count() {
+            1 => base64_decode(content.trim())?,
+            _ => read_armored(&content)?.1,
+        };
+        if data.len() != 42 || &data[..2] != SIG_ALG {
+            bail!("{} is not a {} public key", path, flavor.name());
+        }
+        Ok(Self {
+            flavor,
+            key_id: data[2..10].try_into()?,
+            key: VerifyingKey::from_bytes(data[10..].try_into()?)?,
+        })
+    }
+
+    pub fn to_file_string(&self) -> String {
+        let mut data = SIG_ALG.to_vec();
+        data.extend_from_slice(&self.key_id);
+        data.extend_from_slice(self.key.as_bytes());
+        let comment = match self.flavor {
+            SignifyFlavor::Minisign => format!("minisign public key {}", key_id_hex(&self.key_id)),
+            SignifyFlavor::Signify => "signify public key".to_string(),
+        };
+        armored(&comment, &data)
+    }
+}
+
+impl SignifySecretKey {
+    pub fn generate(flavor: SignifyFlavor) -> Self {
+        let mut key_id = [0u8; 8];
+        OsRng.fill_bytes(&mut key_id);
+        Self {
+            flavor,
+            key_id,
+            key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn public_key(&self) -> SignifyPublicKey {
+        SignifyPublicKey {
+            flavor: self.flavor,
+            key_id: self.key_id,
+            key: self.key.verifying_key(),
+        }
+    }
+
+    // asks for the password only when the key is encrypted
+    pub fn load(flavor: SignifyFlavor, path: &str) -> Result<Self> {
+        let (_, data) = read_armored(&std::fs::read_to_string(path)?)?;
+        let key = match flavor {
+            SignifyFlavor::Minisign => minisign_secret_decode(&data, || {
+                read_passphrase(&format!("Password for {path}: "), PASSPHRASE_ENV)
+            }),
+            SignifyFlavor::Signify => signify_secret_decode(&data, || {
+                read_passphrase(&format!("Passphrase for {path}: "), PASSPHRASE_ENV)
+            }),
+        };
+        key.with_context(|| format!("failed to load {} secret key {}", flavor.name(), path))
+    }
+
+    // an empty password leaves the key unencrypted, like minisign -W and signify -n
+    pub fn to_file_string(&self, password: &str) -> Result<String> {
+        match self.flavor {
+            SignifyFlavor::Minisign => {
+                self.minisign_encode(password, MINISIGN_OPSLIMIT, MINISIGN_MEMLIMIT)
+            }
+            SignifyFlavor::Signify => self.signify_encode(password),
+        }
+    }
+
+    fn minisign_encode(&self, password: &str, opslimit: u64, memlimit: u64) -> Result<String> {
+        let mut keynum_sk = self.key_id.to_vec();
+        keynum_sk.extend_from_slice(&self.key.to_keypair_bytes());
+        let checksum = minisign_checksum(&self.key_id, &keynum_sk[8..]);
+        keynum_sk.extend_from_slice(&checksum);
+
+        let mut salt = [0u8; 32];
+        let (kdf_alg, opslimit, memlimit) = if password.is_empty() {
+            ([0u8; 2], 0, 0)
+        } else {
+            OsRng.fill_bytes(&mut salt);
+            let stream = minisign_kdf(password, &salt, opslimit, memlimit)?;
+            xor(&mut keynum_sk, &stream);
+            (*MINISIGN_KDF_ALG, opslimit, memlimit)
+        };
+        let mut data = SIG_ALG.to_vec();
+        data.extend_from_slice(&kdf_alg);
+        data.extend_from_slice(MINISIGN_CHK_ALG);
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&opslimit.to_le_bytes());
+        data.extend_from_slice(&memlimit.to_le_bytes());
+        data.extend_from_slice(&keynum_sk);
+        let comment = match password.is_empty() {
+            true => "minisign secret key",
+            false => "minisign encrypted secret key",
+        };
+        Ok(armored(comment, &data))
+    }
+
+    fn signify_encode(&self, passphrase: &str) -> Result<String> {
+        let mut seckey = self.key.to_keypair_bytes().to_vec();
+        let checksum = Sha512::digest(&seckey);
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let rounds = match passphrase.is_empty() {
+            true => 0,
+            false => SIGNIFY_KDF_ROUNDS,
+        };
+        if rounds > 0 {
+            let mut stream = [0u8; 64];
+            bcrypt_pbkdf::bcrypt_pbkdf(passphrase, &salt, rounds, &mut stream)
+                .map_err(|e| anyhow!("bcrypt_pbkdf failed: {e}"))?;
+            xor(&mut seckey, &stream);
+        }
+        let mut data = SIG_ALG.to_vec();
+        data.extend_from_slice(SIGNIFY_KDF_ALG);
+        data.extend_from_slice(&rounds.to_be_bytes());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&checksum[..8]);
+        data.extend_from_slice(&self.key_id);
+        data.extend_from_slice(&seckey);
+        Ok(armored("signify secret key", &data))
+    }
+}
+
+impl SignifySigner {
+    pub fn new(key: SignifySecretKey, comment: Option<String>) -> Self {
+        Self { key, comment }
+    }
+
+    pub fn load(flavor: SignifyFlavor, path: &str, comment: Option<String>) -> Result<Self> {
+        Ok(Self::new(SignifySecretKey::load(flavor, path)?, comment))
+    }
+
+    fn sign_named(&self, reader: &mut dyn Read, file_name: Option<&str>) -> Result<Vec<u8>> {
+        let key = &self.key;
+        let mut sig_data = Vec::with_capacity(74);
+        let sig = match key.flavor {
+            SignifyFlavor::Minisign => {
+                sig_data.extend_from_slice(SIG_ALG_HASHED);
+                key.key.sign(&blake2b_reader(reader)?)
+            }
+            SignifyFlavor::Signify => {
+                let mut message = Vec::new();
+                reader.read_to_end(&mut message)?;
+                sig_data.extend_from_slice(SIG_ALG);
+                key.key.sign(&message)
+            }
+        };
+        sig_data.extend_from_slice(&key.key_id);
+        sig_data.extend_from_slice(&sig.to_bytes());
+
+        let text = match key.flavor {
+            SignifyFlavor::Minisign => {
+                let trusted = self.comment.clone().unwrap_or_else(|| {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs());
+                    match file_name {
+                        Some(name) => format!("timestamp:{now}\tfile:{name}\thashed"),
+                        None => format!("timestamp:{now}"),
+                    }
+                });
+                if trusted.contains('\n') {
+                    bail!("the trusted comment must be a single line");
+                }
+                // the global signature covers the trusted comment too
+                let mut global = sig.to_bytes().to_vec();
+                global.extend_from_slice(trusted.as_bytes());
+                let global = key.key.sign(&global);
+                format!(
+                    "{}{}{}\n{}\n",
+                    armored("signature from rcli minisign secret key", &sig_data),
+                    TRUSTED_PREFIX,
+                    trusted,
+                    base64_encode(global.to_bytes())
+                )
+            }
+            SignifyFlavor::Signify => {
+                let comment = match &self.comment {
+                    Some(comment) => comment.clone(),
+                    None => "verify with signify.pub".to_string(),
+                };
+                armored(&comment, &sig_data)
+            }
+        };
+        Ok(text.into_bytes())
+    }
+}
+
+impl TextSign for SignifySigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        self.sign_named(reader, None)
+    }
+
+    fn sign_file(&self, path: &str) -> Result<Vec<u8>> {
+        if path == "-" {
+            return self.sign(&mut std::io::stdin());
+        }
+        let name = Path::new(path).file_name().map(|n| n.to_string_lossy());
+        self.sign_named(&mut std::fs::File::open(path)?, name.as_deref())
+    }
+}
+
+impl SignifyVerifier {
+    pub fn new(key: SignifyPublicKey) -> Self {
+        Self { key }
+    }
+
+    pub fn load(flavor: SignifyFlavor, path: &str) -> Result<Self> {
+        Ok(Self::new(SignifyPublicKey::load(flavor, path)?))
+    }
+}
+
+impl TextVerify for SignifyVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let sig = std::str::from_utf8(sig)?;
+        let (_, sig_data) = read_armored(sig)?;
+        if sig_data.len() != 74 {
+            bail!("invalid {} signature", self.key.flavor.name());
+        }
+        let key_id = &sig_data[2..10];
+        if key_id != self.key.key_id {
+            bail!(
+                "signed with key {}, not with the given key {}",
+                key_id_hex(key_id.try_into()?),
+                key_id_hex(&self.key.key_id)
+            );
+        }
+        let signature = Signature::from_bytes(sig_data[10..].try_into()?);
+        let message = match (&sig_data[..2], self.key.flavor) {
+            (alg, SignifyFlavor::Minisign) if alg == SIG_ALG_HASHED => blake2b_reader(reader)?,
+            (alg, _) if alg == SIG_ALG => {
+                let mut message = Vec::new();
+                reader.read_to_end(&mut message)?;
+                message
+            }
+            (alg, _) => bail!(
+                "unsupported signature algorithm {:?}",
+                String::from_utf8_lossy(alg)
+            ),
+        };
+        if self.key.key.verify(&message, &signature).is_err() {
+            return Ok(false);
+        }
+        if self.key.flavor == SignifyFlavor::Signify {
+            return Ok(true);
+        }
+        let (trusted, global) = minisign_trusted_part(sig)?;
+        let mut signed = signature.to_bytes().to_vec();
+        signed.extend_from_slice(trusted.as_bytes());
+        Ok(self.key.key.verify(&signed, &global).is_ok())
+    }
+}
+
+// the trusted comment of a .minisig, only meaningful once the signature verified
+pub fn minisign_trusted_comment(sig: &str) -> Option<String> {
+    minisign_trusted_part(sig).ok().map(|(comment, _)| comment)
+}
+
+fn minisign_trusted_part(sig: &str) -> Result<(String, Signature)> {
+    let mut lines = sig.lines().skip(2);
+    let trusted = lines
+        .next()
+        .and_then(|l| l.strip_prefix(TRUSTED_PREFIX))
+        .ok_or_else(|| anyhow!("minisign signature has no trusted comment"))?;
+    let global = lines
+        .next()
+        .ok_or_else(|| anyhow!("minisign signature has no global signature"))?;
+    let global = base64_decode(global.trim())?;
+    Ok((trusted.to_string(), Signature::from_slice(&global)?))
+}
+
+fn minisign_secret_decode(
+    data: &[u8],
+    password: impl FnOnce() -> Result<Zeroizing<String>>,
+) -> Result<SignifySecretKey> {
+    if data.len() != 158 || &data[..2] != SIG_ALG || &data[4..6] != MINISIGN_CHK_ALG {
+        bail!("not a minisign secret key");
+    }
+    let salt = &data[6..38];
+    let opslimit = u64::from_le_bytes(data[38..46].try_into()?);
+    let memlimit = u64::from_le_bytes(data[46..54].try_into()?);
+    let mut keynum_sk = data[54..].to_vec();
+    match &data[2..4] {
+        [0, 0] => {}
+        kdf if kdf == MINISIGN_KDF_ALG => {
+            let stream = minisign_kdf(&password()?, salt, opslimit, memlimit)?;
+            xor(&mut keynum_sk, &stream);
+        }
+        kdf => bail!(
+            "unsupported key derivation {:?}",
+            String::from_utf8_lossy(kdf)
+        ),
+    }
+    let key_id: [u8; 8] = keynum_sk[..8].try_into()?;
+    if minisign_checksum(&key_id, &keynum_sk[8..72]) != keynum_sk[72..] {
+        bail!("wrong password or corrupted key");
+    }
+    Ok(SignifySecretKey {
+        flavor: SignifyFlavor::Minisign,
+        key_id,
+        key: SigningKey::from_keypair_bytes(keynum_sk[8..72].try_into()?)?,
+    })
+}
+
+fn signify_secret_decode(
+    data: &[u8],
+    passphrase: impl FnOnce() -> Result<Zeroizing<String>>,
+) -> Result<SignifySecretKey> {
+    if data.len() != 104 || &data[..2] != SIG_ALG || &data[2..4] != SIGNIFY_KDF_ALG {
+        bail!("not a signify secret key");
+    }
+    let rounds = u32::from_be_bytes(data[4..8].try_into()?);
+    let salt = &data[8..24];
+    let checksum = &data[24..32];
+    let key_id: [u8; 8] = data[32..40].try_into()?;
+    let mut seckey = data[40..].to_vec();
+    if rounds > 0 {
+        let mut stream = [0u8; 64];
+        bcrypt_pbkdf::bcrypt_pbkdf(passphrase()?.as_bytes(), salt, rounds, &mut stream)
+            .map_err(|e| anyhow!("bcrypt_pbkdf failed: {e}"))?;
+        xor(&mut seckey, &stream);
+    }
+    if Sha512::digest(&seckey)[..8] != *checksum {
+        bail!("wrong passphrase or corrupted key");
+    }
+    Ok(SignifySecretKey {
+        flavor: SignifyFlavor::Signify,
+        key_id,
+        key: SigningKey::from_keypair_bytes(seckey[..].try_into()?)?,
+    })
+}
+
+// libsodium's crypto_pwhash_scryptsalsa208sha256 turns ops and memory limits into N, r, p
+fn minisign_kdf(password: &str, salt: &[u8], opslimit: u64, memlimit: u64) -> Result<Vec<u8>> {
+    let opslimit = opslimit.max(32768);
+    let r = 8u64;
+    let (log_n, p) = if opslimit < memlimit / 32 {
+        let max_n = opslimit / (r * 4);
+        (scrypt_log_n(max_n), 1)
+    } else {
+        let max_n = memlimit / (r * 128);
+        let log_n = scrypt_log_n(max_n);
+        let max_rp = ((opslimit / 4) >> log_n).min(0x3fff_ffff);
+        (log_n, (max_rp / r) as u32)
+    };
+    let params = scrypt::Params::new(log_n, r as u32, p.max(1), scrypt::Params::RECOMMENDED_LEN)
+        .map_err(|e| anyhow!("invalid scrypt parameters: {e}"))?;
+    let mut stream = vec![0u8; 104];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut stream)
+        .map_err(|e| anyhow!("scrypt failed: {e}"))?;
+    Ok(stream)
+}
+
+fn scrypt_log_n(max_n: u64) -> u8 {
+    (1..63u8).find(|n| (1u64 << n) > max_n / 2).unwrap_or(63)
+}
+
+fn minisign_checksum(key_id: &[u8; 8], secret: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2b::<U32>::new();
+    hasher.update(SIG_ALG);
+    hasher.update(key_id);
+    hasher.update(secret);
+    hasher.finalize().to_vec()
+}
+
+fn blake2b_reader(reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut hasher = Blake2b512::new();
+    std::io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn xor(data: &mut [u8], stream: &[u8]) {
+    data.iter_mut().zip(stream).for_each(|(b, s)| *b ^= s);
+}
+
+fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+fn armored(comment: &str, data: &[u8]) -> String {
+    format!("{UNTRUSTED_PREFIX}{comment}\n{}\n", base64_encode(data))
+}
+
+// an untrusted comment line followed by a base64 line
+fn read_armored(content: &str) -> Result<(String, Vec<u8>)> {
+    let mut lines = content.lines();
+    let comment = lines
+        .next()
+        .and_then(|l| l.strip_prefix(UNTRUSTED_PREFIX))
+        .ok_or_else(|| anyhow!("missing untrusted comment line"))?;
+    let data = lines.next().ok_or_else(|| anyhow!("missing base64 line"))?;
+    Ok((comment.to_string(), base64_decode(data.trim())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify(key: SignifyPublicKey, data: &[u8], sig: &str) -> bool {
+        SignifyVerifier::new(key)
+            .verify(&mut &data[..], sig.as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_minisign_vector() {
+        let key =
+            base64_decode("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3").unwrap();
+        let key = SignifyPublicKey {
+            flavor: SignifyFlavor::Minisign,
+            key_id: key[2..10].try_into().unwrap(),
+            key: VerifyingKey::from_bytes(key[10..].try_into().unwrap()).unwrap(),
+        };
+        let sig = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+        assert!(verify(key, b"test", sig));
+        assert_eq!(
+            minisign_trusted_comment(sig).unwrap(),
+            "timestamp:1555779966\tfile:test"
+        );
+    }
+
+    #[test]
+    fn test_minisign_round_trip() {
+        let key = SignifySecretKey::generate(SignifyFlavor::Minisign);
+        // small scrypt limits keep the test fast, the parameters travel in the key
+        let encoded = key.minisign_encode("secret", 32768, 1 << 20).unwrap();
+        let (_, data) = read_armored(&encoded).unwrap();
+        assert!(minisign_secret_decode(&data, || Ok(Zeroizing::new("wrong".into()))).is_err());
+        let key = minisign_secret_decode(&data, || Ok(Zeroizing::new("secret".into()))).unwrap();
+
+        let public = key.public_key().to_file_string();
+        let public_key = |content: &str| {
+            let (_, data) = read_armored(content).unwrap();
+            SignifyPublicKey {
+                flavor: SignifyFlavor::Minisign,
+                key_id: data[2..10].try_into().unwrap(),
+                key: VerifyingKey::from_bytes(data[10..].try_into().unwrap()).unwrap(),
+            }
+        };
+        let signer = SignifySigner::new(key, Some("release 1.0".into()));
+        let sig = signer.sign(&mut &b"hello"[..]).unwrap();
+        let sig = String::from_utf8(sig).unwrap();
+        assert!(verify(public_key(&public), b"hello", &sig));
+        assert!(!verify(public_key(&public), b"hellO", &sig));
+        // editing the trusted comment breaks the global signature
+        let forged = sig.replace("release 1.0", "release 2.0");
+        assert!(!verify(public_key(&public), b"hello", &forged));
+    }
+
+    #[test]
+    fn test_minisign_encrypted_key_fixture() {
+        // made with libsodium's crypto_pwhash_scryptsalsa208sha256 laid out as minisign -G
+        // does, with limits that map to N=2^10, r=8, p=32 rather than the slow defaults
+        let (_, data) =
+            read_armored(&std::fs::read_to_string("fixtures/minisign.key").unwrap()).unwrap();
+        assert!(minisign_secret_decode(&data, || Ok(Zeroizing::new("wrong".into()))).is_err());
+        let key =
+            minisign_secret_decode(&data, || Ok(Zeroizing::new("correct horse".into()))).unwrap();
+        let public =
+            SignifyPublicKey::load(SignifyFlavor::Minisign, "fixtures/minisign.pub").unwrap();
+        assert_eq!(key.key_id, public.key_id);
+        assert_eq!(key.key.verifying_key(), public.key);
+        let sig = SignifySigner::new(key, None)
+            .sign(&mut &b"hello"[..])
+            .unwrap();
+        assert!(verify(public, b"hello", &String::from_utf8(sig).unwrap()));
+    }
+
+    #[test]
+    fn test_signify_round_trip() {
+        let key = SignifySecretKey::generate(SignifyFlavor::Signify);
+        let (_, data) = read_armored(&key.to_file_string("secret").unwrap()).unwrap();
+        assert!(signify_secret_decode(&data, || Ok(Zeroizing::new("wrong".into()))).is_err());
+        let key = signify_secret_decode(&data, || Ok(Zeroizing::new("secret".into()))).unwrap();
+        let public = key.public_key();
+        let sig = SignifySigner::new(key, None)
+            .sign(&mut &b"hello"[..])
+            .unwrap();
+        let sig = String::from_utf8(sig).unwrap();
+        assert!(sig.starts_with("untrusted comment: verify with signify.pub\n"));
+        assert!(verify(public, b"hello", &sig));
+    }
+}
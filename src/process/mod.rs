@@ -1,13 +1,49 @@
+mod age;
 mod b64;
+mod codec;
 mod convert_csv;
+mod datauri;
+mod derive_pass;
+mod escape;
+mod gen_id;
 mod gen_pass;
+mod hash_file;
+mod hash_pass;
 mod http;
 mod jwt;
+mod key_format;
+mod keyring;
+mod keystore;
+mod minisign;
+mod otp;
+mod pipe;
+mod signature;
+mod sshsig;
+mod stream;
 mod text;
+mod x25519;
 
+pub use age::*;
 pub use b64::*;
+pub use codec::*;
 pub use convert_csv::*;
+pub use datauri::*;
+pub use derive_pass::*;
+pub use escape::*;
+pub use gen_id::*;
 pub use gen_pass::*;
+pub use hash_file::*;
+pub use hash_pass::*;
 pub use http::*;
 pub use jwt::*;
+pub use key_format::*;
+pub use keyring::*;
+pub use keystore::*;
+pub use minisign::*;
+pub use otp::*;
+pub use pipe::*;
+pub use signature::*;
+pub use sshsig::*;
+pub use stream::*;
 pub use text::*;
+pub use x25519::*;
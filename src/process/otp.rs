@@ -0,0 +1,215 @@
+use crate::OtpAlg;
+use anyhow::Result;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use qrcode::{render::unicode, QrCode};
+use rand::{rngs::OsRng, RngCore};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 160 bits, the size recommended by RFC 4226
+const OTP_SECRET_LENGTH: usize = 20;
+
+pub fn otp_secret_gen() -> String {
+    let mut secret = [0u8; OTP_SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+pub fn otp_secret_decode(secret: &str) -> Result<Vec<u8>> {
+    // authenticator apps show secrets grouped, lowercased and sometimes padded
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    Ok(BASE32_NOPAD.decode(secret.as_bytes())?)
+}
+
+pub fn hotp(secret: &[u8], counter: u64, alg: OtpAlg, digits: u8) -> Result<String> {
+    let hash = match alg {
+        OtpAlg::Sha1 => hmac_sign::<Hmac<Sha1>>(secret, counter)?,
+        OtpAlg::Sha256 => hmac_sign::<Hmac<Sha256>>(secret, counter)?,
+        OtpAlg::Sha512 => hmac_sign::<Hmac<Sha512>>(secret, counter)?,
+    };
+    // dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let bin = u32::from_be_bytes(hash[offset..offset + 4].try_into()?) & 0x7fff_ffff;
+    let code = bin as u64 % 10u64.pow(digits as u32);
+    Ok(format!("{code:0width$}", width = digits as usize))
+}
+
+pub fn totp(secret: &[u8], time: u64, period: u64, alg: OtpAlg, digits: u8) -> Result<String> {
+    hotp(secret, time / period, alg, digits)
+}
+
+pub fn totp_now(secret: &[u8], period: u64, alg: OtpAlg, digits: u8) -> Result<String> {
+    totp(secret, unix_now()?, period, alg, digits)
+}
+
+// returns the counter that matched, so callers can resync a HOTP counter
+pub fn hotp_verify(
+    secret: &[u8],
+    code: &str,
+    counter: u64,
+    window: u64,
+    alg: OtpAlg,
+    digits: u8,
+) -> Result<Option<u64>> {
+    // HOTP counters only move forward, so only look ahead
+    for c in counter..=counter.saturating_add(window) {
+        if hotp(secret, c, alg, digits)? == code {
+            return Ok(Some(c));
+        }
+    }
+    Ok(None)
+}
+
+// returns the step offset that matched, 0 means the current period
+pub fn totp_verify(
+    secret: &[u8],
+    code: &str,
+    time: u64,
+    period: u64,
+    window: u64,
+    alg: OtpAlg,
+    digits: u8,
+) -> Result<Option<i64>> {
+    let step = time / period;
+    for c in step.saturating_sub(window)..=step.saturating_add(window) {
+        if hotp(secret, c, alg, digits)? == code {
+            return Ok(Some(c as i64 - step as i64));
+        }
+    }
+    Ok(None)
+}
+
+pub fn totp_verify_now(
+    secret: &[u8],
+    code: &str,
+    period: u64,
+    window: u64,
+    alg: OtpAlg,
+    digits: u8,
+) -> Result<Option<i64>> {
+    totp_verify(secret, code, unix_now()?, period, window, alg, digits)
+}
+
+pub struct OtpUri<'a> {
+    pub secret: &'a str,
+    pub issuer: &'a str,
+    pub account: &'a str,
+    pub alg: OtpAlg,
+    pub digits: u8,
+    pub period: u64,
+    // set for HOTP, None for TOTP
+    pub counter: Option<u64>,
+}
+
+impl OtpUri<'_> {
+    pub fn to_uri(&self) -> String {
+        let issuer = utf8_percent_encode(self.issuer, NON_ALPHANUMERIC);
+        let account = utf8_percent_encode(self.account, NON_ALPHANUMERIC);
+        let (kind, moving_factor) = match self.counter {
+            Some(counter) => ("hotp", format!("counter={counter}")),
+            None => ("totp", format!("period={}", self.period)),
+        };
+        format!(
+            "otpauth://{kind}/{issuer}:{account}?secret={}&issuer={issuer}&algorithm={}&digits={}&{moving_factor}",
+            self.secret,
+            self.alg.to_string().to_uppercase(),
+            self.digits,
+        )
+    }
+}
+
+pub fn qr_render(data: &str) -> Result<String> {
+    let code = QrCode::new(data)?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+fn hmac_sign<M: Mac + hmac::digest::KeyInit>(secret: &[u8], counter: u64) -> Result<Vec<u8>> {
+    let mut mac = <M as Mac>::new_from_slice(secret)?;
+    mac.update(&counter.to_be_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn unix_now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hotp() {
+        // RFC 4226 appendix D
+        let secret = b"12345678901234567890";
+        let expected = ["755224", "287082", "359152", "969429", "338314"];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                &hotp(secret, counter as u64, OtpAlg::Sha1, 6).unwrap(),
+                code
+            );
+        }
+        assert_eq!(
+            hotp_verify(secret, "969429", 1, 2, OtpAlg::Sha1, 6).unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            hotp_verify(secret, "969429", 0, 2, OtpAlg::Sha1, 6).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_totp() {
+        // RFC 6238 appendix B
+        let sha1 = b"12345678901234567890";
+        let sha256 = b"12345678901234567890123456789012";
+        let sha512 = b"1234567890123456789012345678901234567890123456789012345678901234";
+        assert_eq!(totp(sha1, 59, 30, OtpAlg::Sha1, 8).unwrap(), "94287082");
+        assert_eq!(totp(sha256, 59, 30, OtpAlg::Sha256, 8).unwrap(), "46119246");
+        assert_eq!(totp(sha512, 59, 30, OtpAlg::Sha512, 8).unwrap(), "90693936");
+        assert_eq!(
+            totp(sha1, 1111111109, 30, OtpAlg::Sha1, 8).unwrap(),
+            "07081804"
+        );
+
+        assert_eq!(
+            totp_verify(sha1, "94287082", 89, 30, 1, OtpAlg::Sha1, 8).unwrap(),
+            Some(-1)
+        );
+        assert_eq!(
+            totp_verify(sha1, "94287082", 119, 30, 1, OtpAlg::Sha1, 8).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_otp_secret() {
+        let secret = otp_secret_gen();
+        let decoded = otp_secret_decode(&secret.to_lowercase()).unwrap();
+        assert_eq!(decoded.len(), OTP_SECRET_LENGTH);
+
+        let uri = OtpUri {
+            secret: &secret,
+            issuer: "ACME Co",
+            account: "me@example.com",
+            alg: OtpAlg::Sha1,
+            digits: 6,
+            period: 30,
+            counter: None,
+        }
+        .to_uri();
+        assert!(uri.starts_with("otpauth://totp/ACME%20Co:me%40example%2Ecom?secret="));
+        assert!(qr_render(&uri).is_ok());
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{
+    b64_decode, b64_encode, blake3_key_load, get_codec, hash_reader, keyring_resolve,
+    stream_decrypt, stream_encrypt, text_sign, text_verify, B64Format, CodecName, HashAlg, KeyKind,
+    SignMode, TextSignFormat,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use std::{
+    fmt::Display,
+    io::{self, Read, Seek, Write},
+    path::Path,
+    str::FromStr,
+    thread,
+};
+use zeroize::Zeroizing;
+
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+// one step of a recipe, written as `name[:op][(key=value, ...)]`, a value with any of
+// `,|()#"\` in it is double quoted, with `\"` and `\\` inside the quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeStep {
+    pub name: String,
+    pub op: Option<String>,
+    pub params: Vec<(String, String)>,
+}
+
+// steps separated by `|` or new lines, `#` starts a comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipe {
+    pub steps: Vec<PipeStep>,
+}
+
+pub trait PipeStage: Send + Sync {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Encode,
+    Decode,
+}
+
+struct GzipStage(Direction);
+
+struct ZstdStage(Direction, i32);
+
+struct B64Stage(B64Format, Direction);
+
+struct CodecStage(CodecName, Direction);
+
+struct HashStage(HashAlg);
+
+struct ChaChaStage(Zeroizing<[u8; 32]>, Direction);
+
+struct SignStage {
+    format: TextSignFormat,
+    mode: SignMode,
+    key: String,
+}
+
+// passes the data through unchanged when the signature is valid, spooling it to a
+// temporary file meanwhile so nothing unverified reaches the next step
+struct VerifyStage {
+    format: TextSignFormat,
+    key: String,
+    sig: String,
+}
+
+// every stage but the last runs on its own thread, connected by pipes, so data streams
+// through the chain. The last one runs here, as `writer` is usually a locked stdout.
+pub fn pipe_run(
+    recipe: &Recipe,
+    reader: &mut (dyn Read + Send),
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let stages = recipe
+        .steps
+        .iter()
+        .map(|step| Ok((step, build_stage(step)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let Some(((last_step, last), rest)) = stages.split_last() else {
+        io::copy(reader, writer)?;
+        return Ok(());
+    };
+
+    let results = thread::scope(|s| -> Result<Vec<Result<()>>> {
+        let mut handles = Vec::with_capacity(rest.len());
+        let mut input: Box<dyn Read + Send + '_> = Box::new(reader);
+        for (step, stage) in rest {
+            let (next, mut output) = io::pipe()?;
+            let mut stage_input = std::mem::replace(&mut input, Box::new(next));
+            handles.push(s.spawn(move || {
+                let result = stage.run(&mut stage_input, &mut output);
+                result.with_context(|| format!("pipe step {step} failed"))
+            }));
+        }
+        let result = last
+            .run(&mut input, writer)
+            .with_context(|| format!("pipe step {last_step} failed"));
+        // unblock stages still writing, when the last one stopped early
+        drop(input);
+        let mut results: Vec<_> = handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| Err(anyhow!("pipe step panicked")))
+            })
+            .collect();
+        results.push(result);
+        Ok(results)
+    })?;
+
+    // a failed step closes its pipes, the neighbours then see broken pipes or truncated
+    // data, so report the first error that is not a broken pipe
+    let mut errors: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+    let root = errors.iter().position(|e| !is_broken_pipe(e)).unwrap_or(0);
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors.swap_remove(root)),
+    }
+}
+
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.root_cause()
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+}
+
+pub fn build_stage(step: &PipeStep) -> Result<Box<dyn PipeStage>> {
+    let op = step.op.as_deref();
+    let stage: Box<dyn PipeStage> = match step.name.as_str() {
+        "gzip" => Box::new(GzipStage(Direction::Encode)),
+        "gunzip" => Box::new(GzipStage(Direction::Decode)),
+        "zstd" => {
+            let level = match step.param("level") {
+                Some(level) => level.parse().context("invalid zstd level")?,
+                None => ZSTD_DEFAULT_LEVEL,
+            };
+            Box::new(ZstdStage(Direction::Encode, level))
+        }
+        "unzstd" => Box::new(ZstdStage(Direction::Decode, 0)),
+        // b64, b64:decode, b64:url-encode, b64:mime-decode ...
+        "b64" => {
+            let (format, direction) = match op.unwrap_or("encode").rsplit_once('-') {
+                Some((format, direction)) => (format, direction),
+                None => ("std", op.unwrap_or("encode")),
+            };
+            let format: B64Format = format.parse().map_err(|e: &str| anyhow!(e))?;
+            Box::new(B64Stage(format, parse_direction(direction)?))
+        }
+        "hash" => {
+            let alg = op.or(step.param("alg")).unwrap_or("blake3");
+            Box::new(HashStage(alg.parse().map_err(|e: &str| anyhow!(e))?))
+        }
+        "chacha" => {
+            // a key file or @name, a recipe is saved and shouldn't hold the key itself
+            let key = keyring_resolve(step.required_param("key")?, KeyKind::Blake3)?;
+            let key = blake3_key_load(&key)?;
+            let direction = match op {
+                Some("encrypt") => Direction::Encode,
+                Some("decrypt") => Direction::Decode,
+                _ => bail!("chacha needs an operation, chacha:encrypt or chacha:decrypt"),
+            };
+            Box::new(ChaChaStage(key, direction))
+        }
+        "sign" | "verify" => {
+            let format: TextSignFormat = op
+                .or(step.param("format"))
+                .unwrap_or("blake3")
+                .parse()
+                .map_err(|e: &str| anyhow!(e))?;
+            let key = step.required_param("key")?;
+            let key = keyring_resolve(key, format.key_kind(step.name == "sign"))?;
+            if !Path::new(&key).exists() {
+                bail!("key file {} not found", key);
+            }
+            match step.name.as_str() {
+                "sign" => {
+                    let mode = step.param("mode").unwrap_or("pure");
+                    let mode = mode.parse().map_err(|e: &str| anyhow!(e))?;
+                    Box::new(SignStage { format, mode, key })
+                }
+                _ => Box::new(VerifyStage {
+                    format,
+                    key,
+                    sig: step.required_param("sig")?.to_string(),
+                }),
+            }
+        }
+        name => {
+            let codec: CodecName = name
+                .parse()
+                .map_err(|_| anyhow!("unknown pipe step {:?}", name))?;
+            // checks the codec supports the direction before any data flows
+            let direction = parse_direction(op.unwrap_or("encode"))?;
+            get_codec(codec)?;
+            Box::new(CodecStage(codec, direction))
+        }
+    };
+    Ok(stage)
+}
+
+fn parse_direction(s: &str) -> Result<Direction> {
+    match s {
+        "encode" => Ok(Direction::Encode),
+        "decode" => Ok(Direction::Decode),
+        _ => bail!("invalid operation {:?}, expect encode or decode", s),
+    }
+}
+
+impl PipeStep {
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn required_param(&self, key: &str) -> Result<&str> {
+        self.param(key)
+            .ok_or_else(|| anyhow!("pipe step {} needs a {}= parameter", self.name, key))
+    }
+}
+
+impl PipeStage for GzipStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        match self.0 {
+            Direction::Encode => {
+                let mut encoder = GzEncoder::new(writer, Compression::default());
+                io::copy(reader, &mut encoder)?;
+                encoder.finish()?;
+            }
+            Direction::Decode => {
+                io::copy(&mut MultiGzDecoder::new(reader), writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PipeStage for ZstdStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        match self.0 {
+            Direction::Encode => zstd::stream::copy_encode(reader, writer, self.1)?,
+            Direction::Decode => zstd::stream::copy_decode(reader, writer)?,
+        }
+        Ok(())
+    }
+}
+
+impl PipeStage for B64Stage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        match self.1 {
+            Direction::Encode => b64_encode(reader, writer, self.0),
+            Direction::Decode => b64_decode(reader, writer, self.0, false),
+        }
+    }
+}
+
+impl PipeStage for CodecStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let codec = get_codec(self.0)?;
+        match self.1 {
+            Direction::Encode => codec.encode(reader, writer),
+            Direction::Decode => codec.decode(reader, writer),
+        }
+    }
+}
+
+impl PipeStage for HashStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(hash_reader(reader, self.0)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl PipeStage for ChaChaStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        match self.1 {
+            Direction::Encode => stream_encrypt(reader, writer, &self.0[..], false),
+            Direction::Decode => stream_decrypt(reader, writer, &self.0[..]),
+        }
+    }
+}
+
+impl PipeStage for SignStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let sig = text_sign(self.format, self.mode, reader, &self.key)?;
+        writer.write_all(sig.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl PipeStage for VerifyStage {
+    fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut spool = tempfile::tempfile()?;
+        let mut tee = TeeReader(reader, &mut spool);
+        if !text_verify(self.format, &mut tee, &self.key, &self.sig)? {
+            bail!("signature verification failed");
+        }
+        // the verifier may stop short of EOF, the rest still has to be spooled
+        io::copy(&mut tee, &mut io::sink())?;
+        spool.rewind()?;
+        io::copy(&mut spool, writer)?;
+        Ok(())
+    }
+}
+
+struct TeeReader<'a>(&'a mut dyn Read, &'a mut dyn Write);
+
+impl Read for TeeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        self.1.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+// splits on `sep` where it is not inside double quotes
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> Result<String> {
+    let Some(inner) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+    let inner = inner
+        .strip_suffix('"')
+        .ok_or_else(|| anyhow!("unterminated quote in {:?}", value))?;
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(
+                chars
+                    .next()
+                    .ok_or_else(|| anyhow!("dangling escape in {:?}", value))?,
+            ),
+            '"' => bail!("unescaped quote in {:?}", value),
+            c => unquoted.push(c),
+        }
+    }
+    Ok(unquoted)
+}
+
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.contains([',', '|', '(', ')', '#', '"', '\\']);
+    match plain {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+impl FromStr for PipeStep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (head, params) = match s.split_once('(') {
+            Some((head, rest)) => {
+                let params = rest
+                    .strip_suffix(')')
+                    .ok_or_else(|| anyhow!("missing ')' in pipe step {:?}", s))?;
+                (head.trim(), params)
+            }
+            None => (s, ""),
+        };
+        let (name, op) = match head.split_once(':') {
+            Some((name, op)) => (name.trim(), Some(op.trim().to_string())),
+            None => (head, None),
+        };
+        if name.is_empty() {
+            bail!("empty pipe step");
+        }
+        let params = split_unquoted(params, ',')
+            .into_iter()
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| {
+                let (k, v) = p
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("invalid parameter {:?}, expect key=value", p.trim()))?;
+                Ok((k.trim().to_string(), unquote(v.trim())?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(PipeStep {
+            name: name.to_string(),
+            op,
+            params,
+        })
+    }
+}
+
+impl FromStr for Recipe {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = s
+            .lines()
+            .map(|line| split_unquoted(line, '#')[0])
+            .flat_map(|line| split_unquoted(line, '|'))
+            .filter(|step| !step.trim().is_empty())
+            .map(|step| step.parse())
+            .collect::<Result<_>>()?;
+        Ok(Recipe { steps })
+    }
+}
+
+impl Display for PipeStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(op) = &self.op {
+            write!(f, ":{op}")?;
+        }
+        if !self.params.is_empty() {
+            let params: Vec<_> = self
+                .params
+                .iter()
+                .map(|(k, v)| format!("{k}={}", quote(v)))
+                .collect();
+            write!(f, "({})", params.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Recipe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let steps: Vec<_> = self.steps.iter().map(|s| s.to_string()).collect();
+        write!(f, "{}", steps.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(recipe: &str, input: &[u8]) -> Result<Vec<u8>> {
+        let recipe: Recipe = recipe.parse()?;
+        let mut output = Vec::new();
+        pipe_run(&recipe, &mut &input[..], &mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_recipe_parse() {
+        let recipe: Recipe = "gunzip | b64:url-decode\n# comment\nchacha:decrypt(key=k.txt) | hex"
+            .parse()
+            .unwrap();
+        assert_eq!(recipe.steps.len(), 4);
+        assert_eq!(recipe.steps[1].op.as_deref(), Some("url-decode"));
+        assert_eq!(recipe.steps[2].param("key"), Some("k.txt"));
+        assert_eq!(
+            recipe.to_string(),
+            "gunzip | b64:url-decode | chacha:decrypt(key=k.txt) | hex"
+        );
+        assert_eq!(recipe.to_string().parse::<Recipe>().unwrap(), recipe);
+        assert!("hex(key)".parse::<Recipe>().is_err());
+
+        // quoted values keep separators, and survive a round trip
+        let recipe: Recipe = r##"chacha:encrypt(key="a,b|c(1)\"#2\".txt", x=y) | hex # note"##
+            .parse()
+            .unwrap();
+        assert_eq!(recipe.steps.len(), 2);
+        assert_eq!(recipe.steps[0].param("key"), Some(r##"a,b|c(1)"#2".txt"##));
+        assert_eq!(recipe.to_string().parse::<Recipe>().unwrap(), recipe);
+        assert!(r#"hex(key="a)"#.parse::<Recipe>().is_err());
+    }
+
+    #[test]
+    fn test_pipe_run() {
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 7) as u8).collect();
+        let encoded = run("zstd(level=5) | gzip | b64:url-encode", &data).unwrap();
+        let decoded = run("b64:url-decode | gunzip | unzstd", &encoded).unwrap();
+        assert_eq!(decoded, data);
+
+        let encrypted = run("chacha:encrypt(key=fixtures/blake3.txt) | hex", b"hello").unwrap();
+        let decrypted = run(
+            "hex:decode | chacha:decrypt(key=fixtures/blake3.txt)",
+            &encrypted,
+        );
+        assert_eq!(decrypted.unwrap(), b"hello");
+
+        assert_eq!(
+            run("hash:sha256", b"abc").unwrap(),
+            b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(run("", b"abc").unwrap(), b"abc");
+
+        // the error of the failing step wins over the broken pipe upstream
+        let err = run("gzip | b64:decode | hex", &data).unwrap_err();
+        assert!(err.to_string().contains("b64:decode"), "{err:#}");
+        assert!(run("nope", b"").is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    blake3_key_load, ed25519_signing_key_load, ed25519_verifying_key_load, get_reader, text_sign,
+    text_verify, SignMode, TextSignFormat,
+};
+use anyhow::{anyhow, bail, Result};
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+use time::OffsetDateTime;
+
+const SIG_FILE_VERSION: u32 = 1;
+const SIG_FILE_CONTEXT: &str = "rcli 2024-06 signature file v1";
+const FINGERPRINT_CONTEXT: &str = "rcli 2024-06 key fingerprint v1";
+
+// a detached signature that describes itself, written as JSON next to the signed file.
+// What is signed is every other field, the blake3 digest of the content among them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigFile {
+    pub version: u32,
+    pub algorithm: String,
+    pub key_fingerprint: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created: OffsetDateTime,
+    // none when the data came from stdin
+    pub file_name: Option<String>,
+    pub file_size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub digest: String,
+    pub signature: String,
+}
+
+// the canonical form of what a signature file covers, fields in a fixed order
+#[derive(Serialize)]
+struct SignedFields<'a> {
+    context: &'static str,
+    version: u32,
+    algorithm: &'a str,
+    key_fingerprint: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    created: OffsetDateTime,
+    file_name: Option<&'a str>,
+    file_size: u64,
+    comment: Option<&'a str>,
+    digest: &'a str,
+}
+
+// a short, stable id for a key that doesn't reveal it, the public half for ed25519
+pub fn key_fingerprint(key: &[u8]) -> String {
+    let hash = blake3::derive_key(FINGERPRINT_CONTEXT, key);
+    HEXLOWER.encode(&hash[..16])
+}
+
+pub fn signing_key_fingerprint(format: TextSignFormat, key: &str) -> Result<String> {
+    match format {
+        TextSignFormat::Blake3 => Ok(key_fingerprint(blake3_key_load(key)?.as_ref())),
+        TextSignFormat::Ed25519 => {
+            let public = ed25519_signing_key_load(key)?.verifying_key();
+            Ok(key_fingerprint(public.as_bytes()))
+        }
+        TextSignFormat::Minisign | TextSignFormat::Signify | TextSignFormat::Ssh => {
+            native_sig_format(format)
+        }
+    }
+}
+
+pub fn verifying_key_fingerprint(format: TextSignFormat, key: &str) -> Result<String> {
+    match format {
+        TextSignFormat::Blake3 => Ok(key_fingerprint(blake3_key_load(key)?.as_ref())),
+        TextSignFormat::Ed25519 => {
+            let public = ed25519_verifying_key_load(key)?;
+            Ok(key_fingerprint(public.as_bytes()))
+        }
+        TextSignFormat::Minisign | TextSignFormat::Signify | TextSignFormat::Ssh => {
+            native_sig_format(format)
+        }
+    }
+}
+
+fn native_sig_format<T>(format: TextSignFormat) -> Result<T> {
+    bail!("{} writes its own signature files", format)
+}
+
+pub fn sig_file_create(
+    format: TextSignFormat,
+    mode: SignMode,
+    input: &str,
+    key: &str,
+    comment: Option<String>,
+) -> Result<SigFile> {
+    let (digest, file_size) = content_digest(input)?;
+    let file_name = match input {
+        "-" => None,
+        input => Path::new(input)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned()),
+    };
+    let mut sig = SigFile {
+        version: SIG_FILE_VERSION,
+        algorithm: sig_algorithm(format, mode),
+        key_fingerprint: signing_key_fingerprint(format, key)?,
+        created: OffsetDateTime::now_utc().replace_nanosecond(0)?,
+        file_name,
+        file_size,
+        comment,
+        digest,
+        signature: String::new(),
+    };
+    let signature = text_sign(format, mode, &mut sig.signed_message()?.as_slice(), key)?;
+    // the mode already shows in the algorithm, keep the signature itself bare
+    sig.signature = signature
+        .split_once(':')
+        .map_or(signature.as_str(), |(_, sig)| sig)
+        .to_string();
+    Ok(sig)
+}
+
+pub fn sig_file_read(path: &Path) -> Result<SigFile> {
+    let sig: SigFile = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| anyhow!("{} is not a signature file: {}", path.display(), e))?;
+    if sig.version != SIG_FILE_VERSION {
+        bail!("unsupported signature file version {}", sig.version);
+    }
+    Ok(sig)
+}
+
+pub fn sig_file_write(sig: &SigFile, path: &Path) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(sig)? + "\n")?;
+    Ok(())
+}
+
+// checks the key, size and digest recorded in the signature file, then the signature over them
+pub fn sig_file_verify(sig: &SigFile, input: &str, key: &str) -> Result<bool> {
+    let (format, signature) = match sig.algorithm.as_str() {
+        "blake3" => (TextSignFormat::Blake3, sig.signature.clone()),
+        "ed25519" => (TextSignFormat::Ed25519, sig.signature.clone()),
+        alg @ ("ed25519ph" | "ed25519b3") => (
+            TextSignFormat::Ed25519,
+            format!("{}:{}", alg, sig.signature),
+        ),
+        alg => bail!("unknown signature algorithm {:?}", alg),
+    };
+    let fingerprint = verifying_key_fingerprint(format, key)?;
+    if fingerprint != sig.key_fingerprint {
+        bail!(
+            "signed by key {}, but the given key is {}",
+            sig.key_fingerprint,
+            fingerprint
+        );
+    }
+    if input != "-" {
+        let size = fs::metadata(input)?.len();
+        if size != sig.file_size {
+            bail!(
+                "{} is {} bytes, the signature is for {} bytes",
+                input,
+                size,
+                sig.file_size
+            );
+        }
+        let name = Path::new(input).file_name().map(|n| n.to_string_lossy());
+        if let (Some(signed), Some(name)) = (&sig.file_name, name) {
+            if *signed != name {
+                eprintln!("warning: the signature was made for {signed}, checking {name}");
+            }
+        }
+    }
+    if !text_verify(
+        format,
+        &mut sig.signed_message()?.as_slice(),
+        key,
+        &signature,
+    )? {
+        return Ok(false);
+    }
+    let (digest, size) = content_digest(input)?;
+    if digest != sig.digest || size != sig.file_size {
+        bail!("the content of {} doesn't match the signed digest", input);
+    }
+    Ok(true)
+}
+
+impl SigFile {
+    fn signed_message(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&SignedFields {
+            context: SIG_FILE_CONTEXT,
+            version: self.version,
+            algorithm: &self.algorithm,
+            key_fingerprint: &self.key_fingerprint,
+            created: self.created,
+            file_name: self.file_name.as_deref(),
+            file_size: self.file_size,
+            comment: self.comment.as_deref(),
+            digest: &self.digest,
+        })?)
+    }
+}
+
+// blake3 of the content and its size, hashed in parallel for files
+fn content_digest(input: &str) -> Result<(String, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    let size = match input {
+        "-" => {
+            let mut reader = CountReader::new(get_reader(input)?);
+            hasher.update_reader(&mut reader)?;
+            reader.count
+        }
+        path => {
+            hasher.update_mmap_rayon(path)?;
+            fs::metadata(path)?.len()
+        }
+    };
+    Ok((hasher.finalize().to_hex().to_string(), size))
+}
+
+fn sig_algorithm(format: TextSignFormat, mode: SignMode) -> String {
+    match format {
+        TextSignFormat::Ed25519 => mode.sig_prefix().to_string(),
+        _ => format.to_string(),
+    }
+}
+
+struct CountReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> CountReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sig_file() {
+        let sign = |format, key| {
+            sig_file_create(
+                format,
+                SignMode::Blake3,
+                "Cargo.toml",
+                key,
+                Some("v1".into()),
+            )
+            .unwrap()
+        };
+        let sig = sign(TextSignFormat::Ed25519, "fixtures/ed25519.sign");
+        assert_eq!(sig.algorithm, "ed25519b3");
+        assert_eq!(sig.file_name.as_deref(), Some("Cargo.toml"));
+        assert_eq!(
+            sig.key_fingerprint,
+            verifying_key_fingerprint(TextSignFormat::Ed25519, "fixtures/ed25519.verify").unwrap()
+        );
+        let json = serde_json::to_string(&sig).unwrap();
+        let sig: SigFile = serde_json::from_str(&json).unwrap();
+        assert!(sig_file_verify(&sig, "Cargo.toml", "fixtures/ed25519.verify").unwrap());
+        // another file of another size, or the wrong key, fail before the signature is checked
+        assert!(sig_file_verify(&sig, "fixtures/std.txt", "fixtures/ed25519.verify").is_err());
+        assert!(sig_file_verify(&sig, "Cargo.toml", "fixtures/blake3.txt").is_err());
+
+        let mut sig = sign(TextSignFormat::Blake3, "fixtures/blake3.txt");
+        assert_eq!(sig.algorithm, "blake3");
+        assert!(sig_file_verify(&sig, "Cargo.toml", "fixtures/blake3.txt").unwrap());
+        // the metadata is signed too, an edited comment or date no longer verifies
+        sig.comment = Some("edited".into());
+        assert!(!sig_file_verify(&sig, "Cargo.toml", "fixtures/blake3.txt").unwrap());
+        sig.comment = Some("v1".into());
+        sig.created -= time::Duration::days(1);
+        assert!(!sig_file_verify(&sig, "Cargo.toml", "fixtures/blake3.txt").unwrap());
+    }
+}
@@ -0,0 +1,452 @@
+use crate::{
+    ed25519_signing_key_load, get_reader, Ed25519Signer, Ed25519Verifier, SignMode, TextSign,
+    TextVerify,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256, Sha512};
+use ssh_key::{public::KeyData, Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+use std::{fs, io::Read, path::Path};
+use time::{format_description, OffsetDateTime, PrimitiveDateTime};
+
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+// what git and most tools sign files with, ssh-keygen has no default
+pub const SSHSIG_DEFAULT_NAMESPACE: &str = "file";
+
+// signs like ssh-keygen -Y sign, always with a sha512 digest as ssh-keygen does
+pub struct SshSigner {
+    key: SigningKey,
+    namespace: String,
+}
+
+// verifies like ssh-keygen -Y verify against the keys of an allowed_signers file
+pub struct SshVerifier {
+    signers: Vec<AllowedSigner>,
+    namespace: String,
+    identity: Option<String>,
+}
+
+// one line of an allowed_signers file, see ssh-keygen(1)
+#[derive(Debug)]
+pub struct AllowedSigner {
+    // empty for a public key file given instead, which vouches for no identity at all
+    pub principals: Vec<String>,
+    pub namespaces: Option<Vec<String>>,
+    pub cert_authority: bool,
+    // unix times
+    pub valid_after: Option<i64>,
+    pub valid_before: Option<i64>,
+    pub key: PublicKey,
+}
+
+#[derive(Debug)]
+pub struct SshSigCheck {
+    pub principal: String,
+    pub fingerprint: String,
+}
+
+impl SshSigner {
+    pub fn new(key: SigningKey, namespace: impl Into<String>) -> Self {
+        Self {
+            key,
+            namespace: namespace.into(),
+        }
+    }
+
+    // any ed25519 key file works, not just OpenSSH ones
+    pub fn load(path: &str, namespace: impl Into<String>) -> Result<Self> {
+        Ok(Self::new(ed25519_signing_key_load(path)?, namespace))
+    }
+}
+
+impl TextSign for SshSigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        if self.namespace.is_empty() {
+            bail!("ssh signatures need a namespace");
+        }
+        let mut hasher = Sha512::new();
+        std::io::copy(reader, &mut hasher)?;
+        let data = sshsig_signed_data(&self.namespace, &[], HashAlg::Sha512, &hasher.finalize());
+        let sig = Ed25519Signer::new(self.key.clone(), SignMode::Pure).sign(&mut &data[..])?;
+        let public = KeyData::Ed25519(self.key.verifying_key().into());
+        let sig = ssh_key::Signature::new(Algorithm::Ed25519, sig)?;
+        let sig = SshSig::new(public, &self.namespace, HashAlg::Sha512, sig)?;
+        Ok(sig.to_pem(LineEnding::LF)?.into_bytes())
+    }
+}
+
+impl SshVerifier {
+    pub fn new(
+        signers: Vec<AllowedSigner>,
+        namespace: impl Into<String>,
+        identity: Option<String>,
+    ) -> Self {
+        Self {
+            signers,
+            namespace: namespace.into(),
+            identity,
+        }
+    }
+
+    pub fn load(
+        path: &str,
+        namespace: impl Into<String>,
+        identity: Option<String>,
+    ) -> Result<Self> {
+        Ok(Self::new(allowed_signers_load(path)?, namespace, identity))
+    }
+
+    // none when the signature doesn't match the data, an error when the key isn't allowed
+    pub fn check(&self, reader: &mut dyn Read, sig: &str) -> Result<Option<SshSigCheck>> {
+        let sig =
+            SshSig::from_pem(sig.trim()).map_err(|e| anyhow!("invalid ssh signature: {e}"))?;
+        if sig.namespace() != self.namespace {
+            bail!(
+                "the signature is for namespace {:?}, not {:?}",
+                sig.namespace(),
+                self.namespace
+            );
+        }
+        let KeyData::Ed25519(public) = sig.public_key() else {
+            bail!("unsupported ssh signature key {}", sig.algorithm());
+        };
+        let fingerprint = sig.public_key().fingerprint(HashAlg::Sha256).to_string();
+        let signer = self
+            .signers
+            .iter()
+            .find(|s| s.allows(sig.public_key(), &self.namespace, self.identity.as_deref()))
+            .ok_or_else(|| anyhow!("no allowed signer for key {fingerprint}"))?;
+
+        let hash = match sig.hash_alg() {
+            HashAlg::Sha256 => digest_reader::<Sha256>(reader)?,
+            HashAlg::Sha512 => digest_reader::<Sha512>(reader)?,
+            alg => bail!("unsupported ssh signature hash {alg}"),
+        };
+        let data = sshsig_signed_data(sig.namespace(), sig.reserved(), sig.hash_alg(), &hash);
+        let verifier = Ed25519Verifier::new(VerifyingKey::try_from(public)?, SignMode::Pure);
+        if !verifier.verify(&mut &data[..], sig.signature_bytes())? {
+            return Ok(None);
+        }
+        // a bare key says nothing about who holds it, only name it by its own comment
+        let principal = match (&self.identity, signer.principals.is_empty()) {
+            (_, true) if signer.key.comment().is_empty() => fingerprint.clone(),
+            (_, true) => signer.key.comment().to_string(),
+            (Some(identity), false) => identity.clone(),
+            (None, false) => signer.principals.join(","),
+        };
+        Ok(Some(SshSigCheck {
+            principal,
+            fingerprint,
+        }))
+    }
+}
+
+impl TextVerify for SshVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        Ok(self.check(reader, std::str::from_utf8(sig)?)?.is_some())
+    }
+}
+
+impl AllowedSigner {
+    pub fn from_key(key: PublicKey) -> Self {
+        Self {
+            principals: Vec::new(),
+            namespaces: None,
+            cert_authority: false,
+            valid_after: None,
+            valid_before: None,
+            key,
+        }
+    }
+
+    fn allows(&self, key: &KeyData, namespace: &str, identity: Option<&str>) -> bool {
+        if self.cert_authority || self.key.key_data() != key {
+            return false;
+        }
+        if let Some(namespaces) = &self.namespaces {
+            if !pattern_list_match(namespaces, namespace) {
+                return false;
+            }
+        }
+        // a bare key can't be held to an identity, so it never matches one
+        if let Some(identity) = identity {
+            if !pattern_list_match(&self.principals, identity) {
+                return false;
+            }
+        }
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let after = self.valid_after.is_none_or(|t| now >= t);
+        let before = self.valid_before.is_none_or(|t| now < t);
+        after && before
+    }
+}
+
+impl std::str::FromStr for AllowedSigner {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let (first, rest) = next_field(line);
+        if is_key_type(first) {
+            bail!("no principals, a line starts with who the key signs for");
+        }
+        let principals = split_list(first).iter().map(|p| unquote(p)).collect();
+        let (options, key) = match next_field(rest) {
+            (field, _) if is_key_type(field) => ("", rest),
+            (field, rest) => (field, rest),
+        };
+        let mut signer = Self {
+            principals,
+            namespaces: None,
+            cert_authority: false,
+            valid_after: None,
+            valid_before: None,
+            key: PublicKey::from_openssh(key.trim())
+                .map_err(|e| anyhow!("invalid public key: {e}"))?,
+        };
+        for option in split_list(options) {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(unquote(value))),
+                None => (option.as_str(), None),
+            };
+            match (name.to_ascii_lowercase().as_str(), value) {
+                ("cert-authority", None) => signer.cert_authority = true,
+                ("namespaces", Some(value)) => signer.namespaces = Some(split_list(&value)),
+                ("valid-after", Some(value)) => signer.valid_after = Some(ssh_time(&value)?),
+                ("valid-before", Some(value)) => signer.valid_before = Some(ssh_time(&value)?),
+                (name, _) => bail!("unknown allowed_signers option {:?}", name),
+            }
+        }
+        Ok(signer)
+    }
+}
+
+// a single public key file, as ssh-keygen writes id_ed25519.pub, stands in for a file that
+// lists it with no principals, which OpenSSH has no way to say
+pub fn allowed_signers_load(path: &str) -> Result<Vec<AllowedSigner>> {
+    let content = fs::read_to_string(path)?;
+    if !content.trim().contains('\n') {
+        if let Ok(key) = PublicKey::from_openssh(content.trim()) {
+            return Ok(vec![AllowedSigner::from_key(key)]);
+        }
+    }
+    let signers = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            line.parse()
+                .with_context(|| format!("{}:{}: invalid allowed signer", path, i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if signers.is_empty() {
+        bail!("{} has no allowed signers", path);
+    }
+    Ok(signers)
+}
+
+pub fn ssh_sign_file(path: &str, key: &str, namespace: &str) -> Result<String> {
+    let sig = SshSigner::load(key, namespace)?.sign_file(path)?;
+    Ok(String::from_utf8(sig)?)
+}
+
+pub fn ssh_verify_file(
+    path: &str,
+    allowed_signers: &str,
+    sig: &str,
+    namespace: &str,
+    identity: Option<String>,
+) -> Result<Option<SshSigCheck>> {
+    let verifier = SshVerifier::load(allowed_signers, namespace, identity)?;
+    verifier.check(&mut get_reader(path)?, sig)
+}
+
+// an OpenSSH private and public key pair, passphrase protected unless it is empty
+pub fn ssh_key_gen(output_path: &Path, passphrase: &str) -> Result<()> {
+    let mut key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+    key.set_comment("rcli");
+    if !passphrase.is_empty() {
+        key = key.encrypt(&mut OsRng, passphrase)?;
+    }
+    key.write_openssh_file(&output_path.join("id_ed25519"), LineEnding::LF)?;
+    key.public_key()
+        .write_openssh_file(&output_path.join("id_ed25519.pub"))?;
+    Ok(())
+}
+
+fn sshsig_signed_data(namespace: &str, reserved: &[u8], hash_alg: HashAlg, hash: &[u8]) -> Vec<u8> {
+    let mut data = SSHSIG_MAGIC.to_vec();
+    for field in [
+        namespace.as_bytes(),
+        reserved,
+        hash_alg.as_str().as_bytes(),
+        hash,
+    ] {
+        data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+    data
+}
+
+fn digest_reader<D: Digest + std::io::Write>(reader: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut hasher = D::new();
+    std::io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn is_key_type(field: &str) -> bool {
+    ["ssh-", "ecdsa-", "sk-"]
+        .iter()
+        .any(|prefix| field.starts_with(prefix))
+}
+
+// the next whitespace separated field, whitespace inside quotes doesn't count
+fn next_field(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return (&s[..i], &s[i..]),
+            _ => {}
+        }
+    }
+    (s, "")
+}
+
+// comma separated, commas inside quotes don't count
+fn split_list(s: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                items.last_mut().expect("never empty").push(c);
+            }
+            ',' if !quoted => items.push(String::new()),
+            c => items.last_mut().expect("never empty").push(c),
+        }
+    }
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_string()
+}
+
+// YYYYMMDD[HHMM[SS]][Z], all in UTC, ssh-keygen takes times without the Z as local time
+fn ssh_time(t: &str) -> Result<i64> {
+    let digits = t.strip_suffix(['Z', 'z']).unwrap_or(t);
+    if ![8, 12, 14].contains(&digits.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("invalid time {:?}, expect YYYYMMDD[HHMM[SS]][Z]", t);
+    }
+    let format = format_description::parse("[year][month][day][hour][minute][second]")?;
+    let time = PrimitiveDateTime::parse(&format!("{digits:0<14}"), &format)
+        .map_err(|e| anyhow!("invalid time {:?}: {}", t, e))?;
+    Ok(time.assume_utc().unix_timestamp())
+}
+
+// ssh's pattern lists, with * and ? wildcards and !negation
+fn pattern_list_match(patterns: &[String], s: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(pattern) if wildcard_match(pattern.as_bytes(), s.as_bytes()) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(pattern.as_bytes(), s.as_bytes()),
+        }
+    }
+    matched
+}
+
+fn wildcard_match(pattern: &[u8], s: &[u8]) -> bool {
+    match (pattern.split_first(), s.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard_match(rest, s) || (!s.is_empty() && wildcard_match(pattern, &s[1..]))
+        }
+        (Some((b'?', rest)), Some((_, s))) => wildcard_match(rest, s),
+        (Some((p, rest)), Some((c, s))) if p == c => wildcard_match(rest, s),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sshsig_round_trip() {
+        let key = SigningKey::generate(&mut OsRng);
+        let public = PublicKey::new(KeyData::Ed25519(key.verifying_key().into()), "me");
+        let line = format!(
+            "alice@example.com,*@ops.example.com namespaces=\"file,git\" {}",
+            public.to_openssh().unwrap()
+        );
+        let signer = || vec![line.parse::<AllowedSigner>().unwrap()];
+
+        let sig = SshSigner::new(key, "file")
+            .sign(&mut &b"hello"[..])
+            .unwrap();
+        let sig = String::from_utf8(sig).unwrap();
+        assert!(sig.starts_with("-----BEGIN SSH SIGNATURE-----\n"));
+
+        let verifier = SshVerifier::new(signer(), "file", Some("bob@ops.example.com".into()));
+        let check = verifier.check(&mut &b"hello"[..], &sig).unwrap().unwrap();
+        assert_eq!(check.principal, "bob@ops.example.com");
+        assert!(verifier.check(&mut &b"hellO"[..], &sig).unwrap().is_none());
+        // another namespace, identity or an unlisted namespace is refused before the signature
+        let verifier = |namespace, identity: &str| {
+            SshVerifier::new(signer(), namespace, Some(identity.into()))
+                .check(&mut &b"hello"[..], &sig)
+        };
+        assert!(verifier("git", "alice@example.com").is_err());
+        assert!(verifier("file", "mallory@example.com").is_err());
+
+        // a public key file only ever reports its own comment, and matches no claimed identity
+        let bare = || vec![AllowedSigner::from_key(public.clone())];
+        let check = SshVerifier::new(bare(), "file", None)
+            .check(&mut &b"hello"[..], &sig)
+            .unwrap()
+            .unwrap();
+        assert_eq!(check.principal, "me");
+        assert!(
+            SshVerifier::new(bare(), "file", Some("mallory@example.com".into()))
+                .check(&mut &b"hello"[..], &sig)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_allowed_signers_parse() {
+        let key =
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKIeH8HNRK2MUFW8UXHh/fNd0nXOwAtM7VDdcdvDh2HO";
+        let signer: AllowedSigner = format!("\"a b\",c cert-authority {key}").parse().unwrap();
+        assert_eq!(signer.principals, ["a b", "c"]);
+        assert!(signer.cert_authority);
+        assert!(format!("{key} laptop").parse::<AllowedSigner>().is_err());
+        assert!(format!("a nope=1 {key}").parse::<AllowedSigner>().is_err());
+        let signer: AllowedSigner = format!("a valid-after=19700102,valid-before=20990101Z {key}")
+            .parse()
+            .unwrap();
+        assert_eq!(signer.valid_after, Some(86400));
+        assert!(signer.allows(signer.key.key_data(), "file", Some("a")));
+        let signer: AllowedSigner = format!("a valid-before=\"20000101\" {key}")
+            .parse()
+            .unwrap();
+        assert!(!signer.allows(signer.key.key_data(), "file", Some("a")));
+        assert!(format!("a valid-after=2024 {key}")
+            .parse::<AllowedSigner>()
+            .is_err());
+        assert!(pattern_list_match(
+            &["*@x.io".into(), "!eve@x.io".into()],
+            "bob@x.io"
+        ));
+        assert!(!pattern_list_match(
+            &["*@x.io".into(), "!eve@x.io".into()],
+            "eve@x.io"
+        ));
+    }
+}
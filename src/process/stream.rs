@@ -0,0 +1,331 @@
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use data_encoding::BASE64;
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use zeroize::Zeroizing;
+
+const STREAM_INTRO: &[u8] = b"rcli-encrypted-v1\n";
+const STREAM_KEY_INFO: &[u8] = b"rcli 2024-06 stream payload v1";
+pub const STREAM_ARMOR_LABEL: &str = "RCLI ENCRYPTED FILE";
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+pub const STREAM_TAG_SIZE: usize = 16;
+const ARMOR_COLUMNS: usize = 64;
+
+// base64 in 64 column lines between BEGIN and END markers, written as it goes
+pub struct ArmorWriter<'a> {
+    inner: &'a mut dyn Write,
+    label: &'static str,
+    pending: Vec<u8>,
+}
+
+// the other way round, strict about everything between the markers
+pub struct ArmorReader<'a> {
+    inner: &'a mut dyn BufRead,
+    label: &'static str,
+    decoded: Vec<u8>,
+    pos: usize,
+    lines: usize,
+    short_line: bool,
+    done: bool,
+}
+
+impl<'a> ArmorWriter<'a> {
+    pub fn new(inner: &'a mut dyn Write, label: &'static str) -> Result<Self> {
+        writeln!(inner, "-----BEGIN {label}-----")?;
+        Ok(Self {
+            inner,
+            label,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if !self.pending.is_empty() {
+            writeln!(self.inner, "{}", BASE64.encode(&self.pending))?;
+        }
+        writeln!(self.inner, "-----END {}-----", self.label)?;
+        Ok(())
+    }
+}
+
+impl Write for ArmorWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let full = self.pending.len() / 48 * 48;
+        for line in self.pending[..full].chunks(48) {
+            writeln!(self.inner, "{}", BASE64.encode(line))?;
+        }
+        self.pending.drain(..full);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<'a> ArmorReader<'a> {
+    // whitespace before the BEGIN line is fine, and CRLF line ends
+    pub fn new(inner: &'a mut dyn BufRead, label: &'static str) -> Result<Self> {
+        loop {
+            let buf = inner.fill_buf()?;
+            let (len, blank) = (
+                buf.len(),
+                buf.iter().take_while(|b| b.is_ascii_whitespace()).count(),
+            );
+            inner.consume(blank);
+            if len == 0 || blank < len {
+                break;
+            }
+        }
+        let mut reader = Self {
+            inner,
+            label,
+            decoded: Vec::new(),
+            pos: 0,
+            lines: 0,
+            short_line: false,
+            done: false,
+        };
+        if reader.line()? != format!("-----BEGIN {label}-----") {
+            bail!("armored data doesn't start with a BEGIN {} line", label);
+        }
+        Ok(reader)
+    }
+
+    fn line(&mut self) -> Result<String> {
+        let mut line = Vec::new();
+        self.inner.read_until(b'\n', &mut line)?;
+        if line.is_empty() {
+            bail!("armored data has no END {} line", self.label);
+        }
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Ok(String::from_utf8(line.to_vec())?)
+    }
+
+    fn next_line(&mut self) -> Result<()> {
+        let line = self.line()?;
+        if line == format!("-----END {}-----", self.label) {
+            // nothing but whitespace may follow
+            let mut rest = Vec::new();
+            self.inner.read_to_end(&mut rest)?;
+            if self.lines == 0 {
+                bail!("armored data is empty");
+            }
+            if !rest.iter().all(u8::is_ascii_whitespace) {
+                bail!("armored data has garbage after its END line");
+            }
+            self.done = true;
+            return Ok(());
+        }
+        if self.short_line || line.is_empty() || line.len() > ARMOR_COLUMNS {
+            bail!(
+                "armored lines must be {} columns, but the last",
+                ARMOR_COLUMNS
+            );
+        }
+        // padding only ever ends the last line
+        self.short_line = line.len() < ARMOR_COLUMNS || line.ends_with('=');
+        self.lines += 1;
+        self.decoded = BASE64.decode(line.as_bytes())?;
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl Read for ArmorReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.next_line()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+pub fn is_armored(data: &[u8], label: &str) -> bool {
+    data.trim_ascii_start()
+        .starts_with(format!("-----BEGIN {label}-----").as_bytes())
+}
+
+pub fn is_stream(data: &[u8]) -> bool {
+    data.starts_with(STREAM_INTRO) || is_armored(data, STREAM_ARMOR_LABEL)
+}
+
+// a key of its own for every file, from a random nonce, so chunk nonces can count from zero
+pub fn stream_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key: &[u8],
+    armor: bool,
+) -> Result<()> {
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let payload_key = stream_key(key, &nonce)?;
+    let mut reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, reader);
+    match armor {
+        true => {
+            let mut armored = ArmorWriter::new(writer, STREAM_ARMOR_LABEL)?;
+            armored.write_all(STREAM_INTRO)?;
+            armored.write_all(&nonce)?;
+            stream_seal(&payload_key, &mut reader, &mut armored)?;
+            armored.finish()
+        }
+        false => {
+            writer.write_all(STREAM_INTRO)?;
+            writer.write_all(&nonce)?;
+            stream_seal(&payload_key, &mut reader, writer)
+        }
+    }
+}
+
+pub fn stream_decrypt(reader: &mut dyn Read, writer: &mut dyn Write, key: &[u8]) -> Result<()> {
+    let mut reader = BufReader::with_capacity(STREAM_CHUNK_SIZE, reader);
+    if is_armored(reader.fill_buf()?, STREAM_ARMOR_LABEL) {
+        let mut armored = ArmorReader::new(&mut reader, STREAM_ARMOR_LABEL)?;
+        return stream_decrypt_binary(
+            &mut BufReader::with_capacity(STREAM_CHUNK_SIZE, &mut armored),
+            writer,
+            key,
+        );
+    }
+    stream_decrypt_binary(&mut reader, writer, key)
+}
+
+fn stream_decrypt_binary(
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+    key: &[u8],
+) -> Result<()> {
+    let mut intro = [0u8; STREAM_INTRO.len()];
+    let mut nonce = [0u8; 16];
+    reader
+        .read_exact(&mut intro)
+        .map_err(|_| anyhow!("not an rcli encrypted file"))?;
+    if intro != STREAM_INTRO {
+        bail!("not an rcli encrypted file");
+    }
+    reader
+        .read_exact(&mut nonce)
+        .map_err(|_| anyhow!("file is truncated before the payload"))?;
+    let payload_key = stream_key(key, &nonce)?;
+    stream_open(&payload_key, reader, writer)
+}
+
+fn stream_key(key: &[u8], nonce: &[u8; 16]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut payload_key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(nonce), key)
+        .expand(STREAM_KEY_INFO, payload_key.as_mut())
+        .map_err(|_| anyhow!("hkdf expand failed"))?;
+    Ok(payload_key)
+}
+
+// STREAM: 64 KiB chunks, each nonce an 11 byte counter and a last chunk flag, so
+// chunks can't be reordered, dropped or cut off at the end without failing to open
+pub fn stream_seal(key: &[u8; 32], reader: &mut dyn BufRead, writer: &mut dyn Write) -> Result<()> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key)?;
+    let mut chunk = Vec::with_capacity(STREAM_CHUNK_SIZE);
+    for counter in 0u128.. {
+        chunk.clear();
+        reader
+            .take(STREAM_CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        let last = chunk.len() < STREAM_CHUNK_SIZE || reader.fill_buf()?.is_empty();
+        let sealed = cipher
+            .encrypt(&stream_nonce(counter, last), chunk.as_slice())
+            .map_err(|_| anyhow!("payload encryption failed"))?;
+        writer.write_all(&sealed)?;
+        if last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn stream_open(key: &[u8; 32], reader: &mut dyn BufRead, writer: &mut dyn Write) -> Result<()> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key)?;
+    let mut chunk = Vec::with_capacity(STREAM_CHUNK_SIZE + STREAM_TAG_SIZE);
+    for counter in 0u128.. {
+        chunk.clear();
+        reader
+            .take((STREAM_CHUNK_SIZE + STREAM_TAG_SIZE) as u64)
+            .read_to_end(&mut chunk)?;
+        let last = reader.fill_buf()?.is_empty();
+        // only an empty input ends with an empty chunk
+        if chunk.len() < STREAM_TAG_SIZE || (last && counter > 0 && chunk.len() == STREAM_TAG_SIZE)
+        {
+            bail!("payload is truncated or has an empty last chunk");
+        }
+        let opened = cipher
+            .decrypt(&stream_nonce(counter, last), chunk.as_slice())
+            .map_err(|_| {
+                anyhow!(
+                    "payload chunk {} failed to decrypt, truncated or tampered",
+                    counter
+                )
+            })?;
+        writer.write_all(&opened)?;
+        if last {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn stream_nonce(counter: u128, last: bool) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..11].copy_from_slice(&counter.to_be_bytes()[5..]);
+    nonce[11] = last as u8;
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_round_trip() {
+        let key = [5u8; 32];
+        for len in [0, 1, STREAM_CHUNK_SIZE, STREAM_CHUNK_SIZE * 2 + 7] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            for armor in [false, true] {
+                let mut encrypted = Vec::new();
+                stream_encrypt(&mut data.as_slice(), &mut encrypted, &key, armor).unwrap();
+                assert!(is_stream(&encrypted));
+                let mut decrypted = Vec::new();
+                stream_decrypt(&mut encrypted.as_slice(), &mut decrypted, &key).unwrap();
+                assert_eq!(decrypted, data);
+                assert!(
+                    stream_decrypt(&mut encrypted.as_slice(), &mut Vec::new(), &[6; 32]).is_err()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_truncation_and_reordering() {
+        let key = [5u8; 32];
+        let data = vec![1u8; STREAM_CHUNK_SIZE * 3];
+        let mut encrypted = Vec::new();
+        stream_encrypt(&mut data.as_slice(), &mut encrypted, &key, false).unwrap();
+        let header = STREAM_INTRO.len() + 16;
+        let sealed = STREAM_CHUNK_SIZE + STREAM_TAG_SIZE;
+        // the last chunk dropped, the second two swapped
+        let truncated = &encrypted[..header + 2 * sealed];
+        assert!(stream_decrypt(&mut &truncated[..], &mut Vec::new(), &key).is_err());
+        let mut swapped = encrypted[..header + sealed].to_vec();
+        swapped.extend_from_slice(&encrypted[header + 2 * sealed..]);
+        swapped.extend_from_slice(&encrypted[header + sealed..header + 2 * sealed]);
+        assert!(stream_decrypt(&mut swapped.as_slice(), &mut Vec::new(), &key).is_err());
+    }
+}
@@ -1,24 +1,49 @@
-use std::{fs, io::Read, ops::Deref, path::Path};
+use std::{
+    fs,
+    io::{self, Read},
+    ops::Deref,
+    path::Path,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chacha20poly1305::{
-    aead::{Aead, AeadCore},
+    aead::{Aead, AeadCore, Payload},
     ChaCha20Poly1305, ChaChaPoly1305, KeyInit, Nonce,
 };
 use ed25519_dalek::{
     Signature, Signer, SigningKey, Verifier, VerifyingKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH,
 };
 use rand::rngs::OsRng;
+use sha2::{Digest, Sha512};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    blake3_key_load, ed25519_signing_key_load, ed25519_verifying_key_load, gen_pass, get_reader,
+    key_write, read_new_passphrase, ssh_key_gen, KeyFormat, KeyMaterial, Keystore, KeystoreKdf,
+    SignMode, SignifyFlavor, SignifySecretKey, SignifySigner, SignifyVerifier, SshSigner,
+    SshVerifier, TextSignFormat, PASSPHRASE_ENV, SSHSIG_DEFAULT_NAMESPACE,
+};
 
-use crate::{base64_decode, gen_pass, get_content, get_data, TextSignFormat};
+// prepended to the blake3 digest an ed25519 key signs in blake3 prehash mode,
+// so such a signature can never be mistaken for one over a message of 32 bytes
+const ED25519_BLAKE3_CONTEXT: &[u8] = b"rcli ed25519 blake3 prehash v1\0";
 
 pub trait TextSign {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
+
+    // files can be hashed from a memory map on all cores, "-" is stdin
+    fn sign_file(&self, path: &str) -> Result<Vec<u8>> {
+        self.sign(&mut get_reader(path)?)
+    }
 }
 
 pub trait TextVerify {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool>;
+
+    fn verify_file(&self, path: &str, sig: &[u8]) -> Result<bool> {
+        self.verify(&mut get_reader(path)?, sig)
+    }
 }
 
 pub struct Blake3Signer {
@@ -37,12 +62,23 @@ impl Blake3Signer {
     }
 }
 
+impl Drop for Blake3Signer {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl TextSign for Blake3Signer {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let hash = blake3::keyed_hash(&self.key, &buf);
-        Ok(hash.as_bytes().to_vec())
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update_reader(reader)?;
+        Ok(hasher.finalize().as_bytes().to_vec())
+    }
+
+    fn sign_file(&self, path: &str) -> Result<Vec<u8>> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        blake3_update_file(&mut hasher, path)?;
+        Ok(hasher.finalize().as_bytes().to_vec())
     }
 }
 
@@ -62,113 +98,312 @@ impl Blake3Verifier {
     }
 }
 
+impl Drop for Blake3Verifier {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
 impl TextVerify for Blake3Verifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let hash = blake3::keyed_hash(&self.key, &buf);
-        Ok(hash.as_bytes() == sig)
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update_reader(reader)?;
+        // blake3::Hash compares in constant time
+        Ok(<[u8; 32]>::try_from(sig).is_ok_and(|sig| hasher.finalize() == sig))
+    }
+
+    fn verify_file(&self, path: &str, sig: &[u8]) -> Result<bool> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        blake3_update_file(&mut hasher, path)?;
+        Ok(<[u8; 32]>::try_from(sig).is_ok_and(|sig| hasher.finalize() == sig))
     }
 }
 
-struct Ed25519Signer {
+pub struct Ed25519Signer {
     key: SigningKey,
+    mode: SignMode,
 }
 
 impl Ed25519Signer {
-    pub fn new(key: SigningKey) -> Self {
-        Self { key }
+    pub fn new(key: SigningKey, mode: SignMode) -> Self {
+        Self { key, mode }
     }
-    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+    pub fn try_new(key: impl AsRef<[u8]>, mode: SignMode) -> Result<Self> {
         let key = key.as_ref();
         let key = (&key[..SECRET_KEY_LENGTH]).try_into()?;
         let key = SigningKey::from_bytes(key);
-        Ok(Self::new(key))
+        Ok(Self::new(key, mode))
     }
 }
 
 impl TextSign for Ed25519Signer {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let sig = self.key.sign(&buf);
+        let sig = match self.mode {
+            SignMode::Pure => {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                self.key.sign(&buf)
+            }
+            SignMode::Prehash => self.key.sign_prehashed(sha512_reader(reader)?, None)?,
+            SignMode::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update_reader(reader)?;
+                self.key.sign(&blake3_prehash_message(hasher))
+            }
+        };
         Ok(sig.to_bytes().to_vec())
     }
+
+    fn sign_file(&self, path: &str) -> Result<Vec<u8>> {
+        let SignMode::Blake3 = self.mode else {
+            return self.sign(&mut get_reader(path)?);
+        };
+        let mut hasher = blake3::Hasher::new();
+        blake3_update_file(&mut hasher, path)?;
+        Ok(self
+            .key
+            .sign(&blake3_prehash_message(hasher))
+            .to_bytes()
+            .to_vec())
+    }
 }
 
-struct Ed25519Verifier {
+pub struct Ed25519Verifier {
     key: VerifyingKey,
+    mode: SignMode,
 }
 
 impl Ed25519Verifier {
-    pub fn new(key: VerifyingKey) -> Self {
-        Self { key }
+    pub fn new(key: VerifyingKey, mode: SignMode) -> Self {
+        Self { key, mode }
     }
-    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+    pub fn try_new(key: impl AsRef<[u8]>, mode: SignMode) -> Result<Self> {
         let key = key.as_ref();
         let key = (&key[..PUBLIC_KEY_LENGTH]).try_into()?;
         let key = VerifyingKey::from_bytes(key)?;
-        Ok(Self::new(key))
+        Ok(Self::new(key, mode))
     }
 }
 
 impl TextVerify for Ed25519Verifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
         let sig = Signature::try_from(sig)?;
-        Ok(self.key.verify(&buf, &sig).is_ok())
+        let valid = match self.mode {
+            SignMode::Pure => {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                self.key.verify(&buf, &sig)
+            }
+            SignMode::Prehash => self
+                .key
+                .verify_prehashed(sha512_reader(reader)?, None, &sig),
+            SignMode::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update_reader(reader)?;
+                self.key.verify(&blake3_prehash_message(hasher), &sig)
+            }
+        };
+        Ok(valid.is_ok())
+    }
+
+    fn verify_file(&self, path: &str, sig: &[u8]) -> Result<bool> {
+        let SignMode::Blake3 = self.mode else {
+            return self.verify(&mut get_reader(path)?, sig);
+        };
+        let sig = Signature::try_from(sig)?;
+        let mut hasher = blake3::Hasher::new();
+        blake3_update_file(&mut hasher, path)?;
+        let message = blake3_prehash_message(hasher);
+        Ok(self.key.verify(&message, &sig).is_ok())
     }
 }
 
-pub fn text_sign(format: TextSignFormat, reader: &mut dyn Read, key: &str) -> Result<String> {
+fn blake3_update_file(hasher: &mut blake3::Hasher, path: &str) -> Result<()> {
+    match path {
+        "-" => hasher.update_reader(io::stdin().lock())?,
+        path => hasher.update_mmap_rayon(path)?,
+    };
+    Ok(())
+}
+
+fn blake3_prehash_message(hasher: blake3::Hasher) -> Vec<u8> {
+    let mut message = ED25519_BLAKE3_CONTEXT.to_vec();
+    message.extend_from_slice(hasher.finalize().as_bytes());
+    message
+}
+
+fn sha512_reader(reader: &mut dyn Read) -> Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher)
+}
+
+fn get_signer(
+    format: TextSignFormat,
+    mode: SignMode,
+    key: &str,
+    comment: Option<&str>,
+) -> Result<Box<dyn TextSign>> {
+    let comment = comment.map(|c| c.to_string());
     let signer: Box<dyn TextSign> = match format {
-        TextSignFormat::Blake3 => {
-            let key = get_content(key)?;
-            Box::new(Blake3Signer::try_new(key)?)
-        }
+        TextSignFormat::Blake3 => Box::new(Blake3Signer::new(*blake3_key_load(key)?)),
         TextSignFormat::Ed25519 => {
-            let key = get_data(key)?;
-            Box::new(Ed25519Signer::try_new(key)?)
+            Box::new(Ed25519Signer::new(ed25519_signing_key_load(key)?, mode))
+        }
+        TextSignFormat::Minisign => {
+            Box::new(SignifySigner::load(SignifyFlavor::Minisign, key, comment)?)
+        }
+        TextSignFormat::Signify => {
+            Box::new(SignifySigner::load(SignifyFlavor::Signify, key, comment)?)
+        }
+        TextSignFormat::Ssh => Box::new(SshSigner::load(key, SSHSIG_DEFAULT_NAMESPACE)?),
+    };
+    Ok(signer)
+}
+
+// ed25519 signatures made from a prehash say so with a "<mode>:" prefix,
+// a bare signature is the original pure ed25519 over the whole message.
+// minisign, signify and ssh signatures are already text in their own format.
+fn encode_sig(format: TextSignFormat, mode: SignMode, sig: Vec<u8>) -> Result<String> {
+    let encoded = match (format, mode) {
+        (format, _) if format.is_native() => String::from_utf8(sig)?,
+        (TextSignFormat::Ed25519, SignMode::Prehash | SignMode::Blake3) => {
+            format!("{}:{}", mode.sig_prefix(), URL_SAFE_NO_PAD.encode(sig))
         }
+        _ => URL_SAFE_NO_PAD.encode(sig),
     };
-    let sig = signer.sign(reader)?;
-    let encoded = URL_SAFE_NO_PAD.encode(sig);
     Ok(encoded)
 }
 
-pub fn text_verify(
+fn get_verifier(
     format: TextSignFormat,
-    reader: &mut dyn Read,
     key: &str,
     sig: &str,
-) -> Result<bool> {
+) -> Result<(Box<dyn TextVerify>, Vec<u8>)> {
+    let flavor = match format {
+        TextSignFormat::Minisign => Some(SignifyFlavor::Minisign),
+        TextSignFormat::Signify => Some(SignifyFlavor::Signify),
+        _ => None,
+    };
+    if let Some(flavor) = flavor {
+        let verifier = SignifyVerifier::load(flavor, key)?;
+        return Ok((Box::new(verifier), sig.as_bytes().to_vec()));
+    }
+    if let TextSignFormat::Ssh = format {
+        let verifier = SshVerifier::load(key, SSHSIG_DEFAULT_NAMESPACE, None)?;
+        return Ok((Box::new(verifier), sig.as_bytes().to_vec()));
+    }
+    let (mode, sig) = match sig.trim().split_once(':') {
+        Some((prefix, sig)) => (SignMode::from_sig_prefix(prefix)?, sig),
+        None => (SignMode::Pure, sig.trim()),
+    };
     let sig = URL_SAFE_NO_PAD.decode(sig)?;
     let verifier: Box<dyn TextVerify> = match format {
-        TextSignFormat::Blake3 => {
-            let key = get_content(key)?;
-            Box::new(Blake3Verifier::try_new(key)?)
-        }
+        TextSignFormat::Blake3 => Box::new(Blake3Verifier::new(*blake3_key_load(key)?)),
         TextSignFormat::Ed25519 => {
-            let key = get_data(key)?;
-            Box::new(Ed25519Verifier::try_new(key)?)
+            Box::new(Ed25519Verifier::new(ed25519_verifying_key_load(key)?, mode))
         }
+        TextSignFormat::Minisign | TextSignFormat::Signify | TextSignFormat::Ssh => unreachable!(),
     };
+    Ok((verifier, sig))
+}
+
+pub fn text_sign(
+    format: TextSignFormat,
+    mode: SignMode,
+    reader: &mut dyn Read,
+    key: &str,
+) -> Result<String> {
+    let sig = get_signer(format, mode, key, None)?.sign(reader)?;
+    encode_sig(format, mode, sig)
+}
+
+// the comment is the trusted comment of minisign and the untrusted one of signify
+pub fn text_sign_file(
+    format: TextSignFormat,
+    mode: SignMode,
+    path: &str,
+    key: &str,
+    comment: Option<&str>,
+) -> Result<String> {
+    let sig = get_signer(format, mode, key, comment)?.sign_file(path)?;
+    encode_sig(format, mode, sig)
+}
+
+pub fn text_verify(
+    format: TextSignFormat,
+    reader: &mut dyn Read,
+    key: &str,
+    sig: &str,
+) -> Result<bool> {
+    let (verifier, sig) = get_verifier(format, key, sig)?;
     verifier.verify(reader, &sig)
 }
 
-pub fn key_gen(format: TextSignFormat, output_path: &Path) -> Result<()> {
+pub fn text_verify_file(format: TextSignFormat, path: &str, key: &str, sig: &str) -> Result<bool> {
+    let (verifier, sig) = get_verifier(format, key, sig)?;
+    verifier.verify_file(path, &sig)
+}
+
+pub fn key_gen(
+    format: TextSignFormat,
+    output_path: &Path,
+    key_format: KeyFormat,
+    encrypt: bool,
+) -> Result<()> {
+    if format.is_native() && (key_format != KeyFormat::Raw || encrypt) {
+        bail!("{} keys have their own file format", format);
+    }
+    match key_format {
+        KeyFormat::Keystore => bail!("use --encrypt to seal the secret key in a keystore"),
+        // 64 hex digits read as raw text too, so rcli couldn't load the key back
+        KeyFormat::Hex => {
+            bail!("hex keys read back as raw text, export one with key convert --to hex")
+        }
+        _ => {}
+    }
+    let write_secret = |path: &Path, key: &KeyMaterial| match encrypt {
+        true => {
+            let passphrase = read_new_passphrase("Passphrase for the key: ", PASSPHRASE_ENV)?;
+            Keystore::seal(key, &passphrase, KeystoreKdf::default())?.write(path)
+        }
+        false => key_write(path, key, key_format),
+    };
     match format {
         TextSignFormat::Blake3 => {
             let key = gen_pass(32, false, false, false, false)?;
-            fs::write(output_path.join("blake3.txt"), key)?;
+            let key = KeyMaterial::Blake3(Zeroizing::new(key.as_bytes().try_into()?));
+            write_secret(&output_path.join("blake3.txt"), &key)?;
         }
         TextSignFormat::Ed25519 => {
             let mut os_rng = OsRng;
-            let signing_key = SigningKey::generate(&mut os_rng);
-            let verifying_key = signing_key.verifying_key();
-            fs::write(output_path.join("ed25519.sign"), signing_key.to_bytes())?;
-            fs::write(output_path.join("ed25519.verify"), verifying_key.to_bytes())?;
+            let key = KeyMaterial::Ed25519Sign(SigningKey::generate(&mut os_rng));
+            write_secret(&output_path.join("ed25519.sign"), &key)?;
+            key_write(
+                &output_path.join("ed25519.verify"),
+                &key.public()?,
+                key_format,
+            )?;
+        }
+        TextSignFormat::Minisign | TextSignFormat::Signify => {
+            let (flavor, name, secret_ext) = match format {
+                TextSignFormat::Minisign => (SignifyFlavor::Minisign, "minisign", "key"),
+                _ => (SignifyFlavor::Signify, "signify", "sec"),
+            };
+            let password = read_new_passphrase(
+                "Password for the secret key, empty for none: ",
+                PASSPHRASE_ENV,
+            )?;
+            let key = SignifySecretKey::generate(flavor);
+            let secret = key.to_file_string(&password)?;
+            fs::write(output_path.join(format!("{name}.{secret_ext}")), secret)?;
+            let public = key.public_key().to_file_string();
+            fs::write(output_path.join(format!("{name}.pub")), public)?;
+        }
+        TextSignFormat::Ssh => {
+            let passphrase =
+                read_new_passphrase("Passphrase for the key, empty for none: ", PASSPHRASE_ENV)?;
+            ssh_key_gen(output_path, &passphrase)?;
         }
     }
     Ok(())
@@ -198,42 +433,60 @@ impl Deref for ChaCha {
     }
 }
 
-impl DataEncrypt for ChaCha {
-    fn data_encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+// nonce || ciphertext, the associated data is authenticated but not stored
+impl ChaCha {
+    fn seal(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
         let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = self
+            .encrypt(&nonce, Payload { msg: data, aad })
+            .map_err(|_| anyhow!("chacha20poly1305 encryption failed"))?;
         let mut buf = nonce.to_vec();
-        eprintln!("nonce_len={}", nonce.len());
-        let enc = self.encrypt(&nonce, data).unwrap();
-        eprintln!("enc_len={}", enc.len());
-        buf.extend(enc);
-        eprintln!("total_len={}", buf.len());
+        buf.extend(encrypted);
         Ok(buf)
     }
+
+    fn open(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < 12 {
+            bail!("encrypted data is too short to hold a nonce");
+        }
+        let nonce = Nonce::from_slice(&data[..12]);
+        self.decrypt(
+            nonce,
+            Payload {
+                msg: &data[12..],
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("decryption failed, wrong key or corrupted data"))
+    }
+}
+
+impl DataEncrypt for ChaCha {
+    fn data_encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.seal(data, &[])
+    }
 }
 
 impl DataDecrypt for ChaCha {
     fn data_decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let nonce = Nonce::from_slice(&data[..12]);
-        Ok(self.decrypt(nonce, &data[12..]).unwrap())
+        self.open(data, &[])
     }
 }
 
-pub fn data_encrypt(data_reader: &mut dyn Read, key: impl AsRef<[u8]>) -> Result<Vec<u8>> {
-    let mut data = Vec::new();
-    data_reader.read_to_end(&mut data)?;
-    let key = key.as_ref();
-    eprintln!("key_len={}, data_len={}", key.len(), data.len());
-    let chacha = ChaCha::try_new(key)?;
-    chacha.data_encrypt(&data)
+pub fn chacha_seal(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    ChaCha::try_new(key)?.data_encrypt(data)
+}
+
+pub fn chacha_open(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    ChaCha::try_new(key)?.data_decrypt(data)
 }
 
-pub fn data_decrypt(data_reader: &mut dyn Read, key: impl AsRef<[u8]>) -> Result<Vec<u8>> {
-    let mut b64_string = String::new();
-    data_reader.read_to_string(&mut b64_string)?;
-    let encrypted = base64_decode(&b64_string)?;
-    eprintln!("encrypted_total_len={}", encrypted.len());
-    let chacha = ChaCha::try_new(key.as_ref())?;
-    chacha.data_decrypt(&encrypted)
+pub fn chacha_seal_aad(data: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    ChaCha::try_new(key)?.seal(data, aad)
+}
+
+pub fn chacha_open_aad(data: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    ChaCha::try_new(key)?.open(data, aad)
 }
 
 #[cfg(test)]
@@ -243,8 +496,13 @@ mod tests {
     #[test]
     fn test_blake3() {
         let mut reader = "hello".as_bytes();
-        let encoded =
-            text_sign(TextSignFormat::Blake3, &mut reader, "fixtures/blake3.txt").unwrap();
+        let encoded = text_sign(
+            TextSignFormat::Blake3,
+            SignMode::Pure,
+            &mut reader,
+            "fixtures/blake3.txt",
+        )
+        .unwrap();
         let mut reader = "hello".as_bytes();
         let valid = text_verify(
             TextSignFormat::Blake3,
@@ -254,26 +512,71 @@ mod tests {
         )
         .unwrap();
         assert!(valid);
+
+        // the memory mapped path gives the same MAC as the streamed one
+        let mut reader = fs::File::open("Cargo.toml").unwrap();
+        let key = "fixtures/blake3.txt";
+        let streamed = text_sign(TextSignFormat::Blake3, SignMode::Pure, &mut reader, key);
+        let mapped = text_sign_file(
+            TextSignFormat::Blake3,
+            SignMode::Pure,
+            "Cargo.toml",
+            key,
+            None,
+        );
+        assert_eq!(streamed.unwrap(), mapped.unwrap());
     }
 
     #[test]
     fn test_ed25519() {
+        for mode in [SignMode::Pure, SignMode::Prehash, SignMode::Blake3] {
+            let encoded = text_sign_file(
+                TextSignFormat::Ed25519,
+                mode,
+                "Cargo.toml",
+                "fixtures/ed25519.sign",
+                None,
+            )
+            .unwrap();
+            let mut reader = fs::File::open("Cargo.toml").unwrap();
+            let valid = text_verify(
+                TextSignFormat::Ed25519,
+                &mut reader,
+                "fixtures/ed25519.verify",
+                &encoded,
+            )
+            .unwrap();
+            assert!(valid, "{mode}");
+            let mut reader = "hello".as_bytes();
+            let valid = text_verify(
+                TextSignFormat::Ed25519,
+                &mut reader,
+                "fixtures/ed25519.verify",
+                &encoded,
+            )
+            .unwrap();
+            assert!(!valid, "{mode}");
+        }
+
+        // the mode is part of the signature, pure ones stay bare as before
+        let sign = |mode| {
+            let mut reader = "hello".as_bytes();
+            text_sign(
+                TextSignFormat::Ed25519,
+                mode,
+                &mut reader,
+                "fixtures/ed25519.sign",
+            )
+            .unwrap()
+        };
+        assert!(!sign(SignMode::Pure).contains(':'));
+        assert!(sign(SignMode::Prehash).starts_with("ed25519ph:"));
+        assert!(sign(SignMode::Blake3).starts_with("ed25519b3:"));
+        let tampered = sign(SignMode::Blake3).replace("ed25519b3:", "ed25519ph:");
         let mut reader = "hello".as_bytes();
-        let encoded = text_sign(
-            TextSignFormat::Ed25519,
-            &mut reader,
-            "fixtures/ed25519.sign",
-        )
-        .unwrap();
-        let mut reader = "hello".as_bytes();
-        let valid = text_verify(
-            TextSignFormat::Ed25519,
-            &mut reader,
-            "fixtures/ed25519.verify",
-            &encoded,
-        )
-        .unwrap();
-        assert!(valid);
+        let key = "fixtures/ed25519.verify";
+        let valid = text_verify(TextSignFormat::Ed25519, &mut reader, key, &tampered);
+        assert!(!valid.unwrap());
     }
 
     #[test]
@@ -0,0 +1,218 @@
+use crate::{
+    base64_decode, base64_encode, chacha_open, chacha_seal, ed25519_signing_key_load,
+    ed25519_verifying_key_load, keyring_resolve, KeyKind,
+};
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, KeyInit, Nonce};
+use data_encoding::HEXLOWER_PERMISSIVE;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+const SEALED_BOX_VERSION: u32 = 1;
+const SEALED_BOX_CIPHER: &str = "x25519-hkdf-sha256-chacha20-poly1305";
+const WRAP_KEY_INFO: &[u8] = b"rcli 2024-06 x25519 file key v1";
+
+// data encrypted once under a random file key, and that key wrapped for each recipient
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedBox {
+    pub version: u32,
+    pub cipher: String,
+    pub recipients: Vec<SealedStanza>,
+    // nonce and ciphertext, base64
+    pub ciphertext: String,
+}
+
+// says nothing about who it is for, a recipient finds theirs by unwrapping
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedStanza {
+    pub ephemeral: String,
+    pub wrapped_key: String,
+}
+
+pub struct X25519Recipient(PublicKey);
+
+pub struct X25519Identity(StaticSecret);
+
+impl X25519Recipient {
+    // a key file or @name of an ed25519 key, or an x25519 public key in hex
+    pub fn load(recipient: &str) -> Result<Self> {
+        let path = keyring_resolve(recipient, KeyKind::Ed25519Verify)?;
+        if !Path::new(&path).exists() {
+            let key: [u8; 32] = HEXLOWER_PERMISSIVE
+                .decode(path.as_bytes())
+                .ok()
+                .and_then(|k| k.try_into().ok())
+                .ok_or_else(|| {
+                    anyhow!("recipient {} is not a key file or x25519 public key", path)
+                })?;
+            return Ok(Self(PublicKey::from(key)));
+        }
+        Ok(Self::from_ed25519(&ed25519_verifying_key_load(&path)?))
+    }
+
+    pub fn from_ed25519(key: &VerifyingKey) -> Self {
+        Self(PublicKey::from(key.to_montgomery().to_bytes()))
+    }
+
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(PublicKey::from(key))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+impl X25519Identity {
+    pub fn load(identity: &str) -> Result<Self> {
+        Ok(Self::from_ed25519(&ed25519_signing_key_load(
+            &keyring_resolve(identity, KeyKind::Ed25519Sign)?,
+        )?))
+    }
+
+    // the same scalar the ed25519 key signs with, so the public halves correspond
+    pub fn from_ed25519(key: &SigningKey) -> Self {
+        Self(StaticSecret::from(key.to_scalar_bytes()))
+    }
+
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(StaticSecret::from(key))
+    }
+
+    pub fn recipient(&self) -> X25519Recipient {
+        X25519Recipient(PublicKey::from(&self.0))
+    }
+
+    // refuses low order points, whose shared secret anyone can guess
+    pub fn shared(&self, public: &[u8; 32]) -> Result<Zeroizing<[u8; 32]>> {
+        let shared = self.0.diffie_hellman(&PublicKey::from(*public));
+        if !shared.was_contributory() {
+            bail!("x25519 public key is a low order point");
+        }
+        Ok(Zeroizing::new(shared.to_bytes()))
+    }
+}
+
+impl SealedBox {
+    pub fn seal(data: &[u8], recipients: &[X25519Recipient]) -> Result<Self> {
+        if recipients.is_empty() {
+            bail!("no recipients to encrypt to");
+        }
+        let mut file_key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(file_key.as_mut());
+        let recipients = recipients
+            .iter()
+            .map(|recipient| stanza_wrap(&file_key, recipient))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            version: SEALED_BOX_VERSION,
+            cipher: SEALED_BOX_CIPHER.to_string(),
+            recipients,
+            ciphertext: base64_encode(chacha_seal(data, file_key.as_ref())?),
+        })
+    }
+
+    pub fn open(&self, identity: &X25519Identity) -> Result<Vec<u8>> {
+        if self.cipher != SEALED_BOX_CIPHER {
+            bail!("unsupported sealed box cipher {:?}", self.cipher);
+        }
+        let file_key = self
+            .recipients
+            .iter()
+            .find_map(|stanza| stanza_unwrap(stanza, identity).ok())
+            .ok_or_else(|| anyhow!("not encrypted to this key"))?;
+        chacha_open(&base64_decode(&self.ciphertext)?, file_key.as_ref())
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let sealed: Self = serde_json::from_str(content)?;
+        if sealed.version != SEALED_BOX_VERSION {
+            bail!("unsupported sealed box version {}", sealed.version);
+        }
+        Ok(sealed)
+    }
+}
+
+fn stanza_wrap(file_key: &[u8; 32], recipient: &X25519Recipient) -> Result<SealedStanza> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient.0);
+    if !shared.was_contributory() {
+        bail!("recipient is a low order x25519 point");
+    }
+    let wrap_key = wrap_key_derive(shared.as_bytes(), &ephemeral_public, &recipient.0)?;
+    let wrapped = ChaCha20Poly1305::new_from_slice(wrap_key.as_ref())?
+        .encrypt(&Nonce::default(), file_key.as_ref())
+        .map_err(|_| anyhow!("file key wrapping failed"))?;
+    Ok(SealedStanza {
+        ephemeral: base64_encode(ephemeral_public.as_bytes()),
+        wrapped_key: base64_encode(wrapped),
+    })
+}
+
+fn stanza_unwrap(stanza: &SealedStanza, identity: &X25519Identity) -> Result<Zeroizing<Vec<u8>>> {
+    let ephemeral: [u8; 32] = base64_decode(&stanza.ephemeral)?
+        .try_into()
+        .map_err(|_| anyhow!("ephemeral key is not 32 bytes"))?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let shared = identity.0.diffie_hellman(&ephemeral);
+    if !shared.was_contributory() {
+        bail!("ephemeral key is a low order x25519 point");
+    }
+    let wrap_key = wrap_key_derive(shared.as_bytes(), &ephemeral, &identity.recipient().0)?;
+    let file_key = ChaCha20Poly1305::new_from_slice(wrap_key.as_ref())?
+        .decrypt(
+            &Nonce::default(),
+            base64_decode(&stanza.wrapped_key)?.as_ref(),
+        )
+        .map_err(|_| anyhow!("the file key is wrapped for another recipient"))?;
+    Ok(Zeroizing::new(file_key))
+}
+
+// each wrap key is used once, for a fresh ephemeral key, so the zero nonce is safe
+fn wrap_key_derive(
+    shared: &[u8; 32],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<Zeroizing<[u8; 32]>> {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_KEY_INFO, key.as_mut())
+        .map_err(|_| anyhow!("hkdf expand failed"))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_box_recipients() {
+        let alice = X25519Identity::from_ed25519(&SigningKey::from_bytes(&[1; 32]));
+        let bob = X25519Identity::from_ed25519(&SigningKey::from_bytes(&[2; 32]));
+        let carol = X25519Identity::from_ed25519(&SigningKey::from_bytes(&[3; 32]));
+        let sealed =
+            SealedBox::seal(b"hello world", &[alice.recipient(), bob.recipient()]).unwrap();
+        let sealed = SealedBox::parse(&serde_json::to_string(&sealed).unwrap()).unwrap();
+        assert_eq!(sealed.recipients.len(), 2);
+        assert_eq!(sealed.open(&alice).unwrap(), b"hello world");
+        assert_eq!(sealed.open(&bob).unwrap(), b"hello world");
+        assert!(sealed.open(&carol).is_err());
+
+        // the recipient derived from the ed25519 public key is the identity's
+        let signing = SigningKey::from_bytes(&[1; 32]);
+        let recipient = X25519Recipient::from_ed25519(&signing.verifying_key());
+        assert_eq!(recipient.to_bytes(), alice.recipient().to_bytes());
+    }
+}
@@ -1,8 +1,17 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use std::{
     fs::{self, File},
-    io::{self, Read},
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
 };
+use zeroize::Zeroizing;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const PASSPHRASE_ENV: &str = "RCLI_PASSPHRASE";
+// the new passphrase of key passwd, RCLI_PASSPHRASE is the current one
+pub const NEW_PASSPHRASE_ENV: &str = "RCLI_NEW_PASSPHRASE";
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     if input == "-" {
@@ -12,6 +21,19 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     }
 }
 
+// same as get_reader, optionally reporting progress and throughput on stderr
+pub fn get_progress_reader(input: &str, progress: bool) -> Result<Box<dyn Read>> {
+    let reader = get_reader(input)?;
+    if !progress {
+        return Ok(reader);
+    }
+    let total = match input {
+        "-" => None,
+        _ => Some(fs::metadata(input)?.len()),
+    };
+    Ok(Box::new(ProgressReader::new(reader, total)))
+}
+
 pub fn get_content(path: &str) -> Result<String> {
     Ok(fs::read_to_string(path)?)
 }
@@ -19,3 +41,167 @@ pub fn get_content(path: &str) -> Result<String> {
 pub fn get_data(path: &str) -> Result<Vec<u8>> {
     Ok(fs::read(path)?)
 }
+
+// prompt without echo on a terminal, otherwise take the first line of piped stdin
+pub fn read_secret(prompt: &str) -> Result<String> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        Ok(rpassword::prompt_password(prompt)?)
+    } else {
+        let mut line = String::new();
+        stdin.read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+// for scripts: the passphrase itself in RCLI_PASSPHRASE, or a file descriptor to read
+// it from in RCLI_PASSPHRASE_FD, otherwise it is asked for like any secret
+pub fn read_passphrase(prompt: &str, env: &str) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(env) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+    if let Ok(fd) = std::env::var(format!("{env}_FD")) {
+        let fd: u32 = fd
+            .parse()
+            .map_err(|_| anyhow!("{}_FD is not a file descriptor", env))?;
+        let content = Zeroizing::new(fs::read_to_string(format!("/dev/fd/{fd}"))?);
+        let line = content.lines().next().unwrap_or_default();
+        return Ok(Zeroizing::new(line.to_string()));
+    }
+    Ok(Zeroizing::new(read_secret(prompt)?))
+}
+
+// a passphrase being set is typed twice on a terminal
+pub fn read_new_passphrase(prompt: &str, env: &str) -> Result<Zeroizing<String>> {
+    let passphrase = read_passphrase(prompt, env)?;
+    if std::env::var_os(env).is_none()
+        && std::env::var_os(format!("{env}_FD")).is_none()
+        && io::stdin().is_terminal()
+        && *Zeroizing::new(read_secret("Again: ")?) != *passphrase
+    {
+        bail!("passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+// for secret keys, only readable by their owner, ssh insists on it
+pub fn write_private_file(path: &Path, data: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // the mode only applies to a new file, an existing one keeps its own
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+// treat anything that is not printable UTF-8 text as binary
+pub fn is_binary(data: &[u8]) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(s) => s
+            .chars()
+            .any(|c| c.is_control() && !c.is_ascii_whitespace()),
+        Err(_) => true,
+    }
+}
+
+// `hexdump -C` style: offset, 16 hex bytes, printable ascii
+pub fn hexdump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (j, b) in chunk.iter().enumerate() {
+            if j == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{b:02x} "));
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!("{:08x}  {hex:<49} |{ascii}|\n", i * 16));
+    }
+    out.push_str(&format!("{:08x}", data.len()));
+    out
+}
+
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    total: Option<u64>,
+    read: u64,
+    start: Instant,
+    last_report: Instant,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total: Option<u64>) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            total,
+            read: 0,
+            start: now,
+            last_report: now,
+        }
+    }
+
+    fn report(&self) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let rate = human_bytes((self.read as f64 / elapsed) as u64);
+        match self.total {
+            Some(total) if total > 0 => format!(
+                "{} / {} ({:.0}%), {rate}/s",
+                human_bytes(self.read),
+                human_bytes(total),
+                self.read as f64 * 100.0 / total as f64
+            ),
+            _ => format!("{}, {rate}/s", human_bytes(self.read)),
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            eprint!("\r\x1b[2K{}", self.report());
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        eprintln!(
+            "\r\x1b[2K{} in {:.2}s",
+            self.report(),
+            self.start.elapsed().as_secs_f64()
+        );
+    }
+}
+
+pub fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = n as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{n} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}